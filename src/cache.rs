@@ -0,0 +1,443 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use songbird::input::Metadata;
+use tokio::{process::Command as TokioCommand, task, time::timeout};
+
+use crate::{
+    store::{load_json, save_json},
+    ytdl::{from_beginning, YTDL_COMMAND},
+};
+
+const INDEX_FILE: &str = "index.json";
+const DEFAULT_CACHE_SIZE_MB: u64 = 2048;
+/// Longest a download may take before it's given up on.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// A downloaded song, with the metadata needed to queue it without asking yt-dlp.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct CachedSong {
+    pub(crate) title: String,
+    pub(crate) url: String,
+    pub(crate) duration: Option<Duration>,
+    pub(crate) author: Option<String>,
+    pub(crate) thumbnail: Option<String>,
+    /// Size of the audio file in bytes, checked whenever it's opened.
+    pub(crate) size: u64,
+    /// SHA-256 of the audio file, checked whenever it's played from the start.
+    pub(crate) hash: String,
+    pub(crate) plays: u64,
+    pub(crate) last_used: DateTime<Utc>,
+}
+
+impl CachedSong {
+    pub(crate) fn metadata(&self) -> Metadata {
+        Metadata {
+            title: Some(self.title.clone()),
+            source_url: Some(self.url.clone()),
+            duration: self.duration,
+            artist: self.author.clone(),
+            thumbnail: self.thumbnail.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The cache key for `url`, if it's a YouTube video, like `youtube-dQw4w9WgXcQ`.
+pub(crate) fn video_id(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let (host, path) = rest.split_once('/')?;
+    let host = host
+        .trim_start_matches("www.")
+        .trim_start_matches("m.")
+        .trim_start_matches("music.");
+    let path = path.split('#').next()?;
+
+    let id = match host {
+        "youtu.be" => path.split('?').next()?,
+        "youtube.com" => match path.strip_prefix("shorts/") {
+            Some(short) => short.split(['?', '/']).next()?,
+            None => path
+                .strip_prefix("watch?")?
+                .split('&')
+                .find_map(|param| param.strip_prefix("v="))?,
+        },
+        _ => return None,
+    };
+
+    let valid = id.len() == 11
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| format!("youtube-{id}"))
+}
+
+/// Shared on-disk cache of downloaded songs by video ID, evicting the least recently played songs
+/// once it's full.
+#[derive(Clone, Debug)]
+pub(crate) struct AudioCache {
+    dir: PathBuf,
+    /// Largest total size of the cached audio, in bytes.
+    max_size: u64,
+    songs: Arc<Mutex<HashMap<String, CachedSong>>>,
+    downloading: Arc<Mutex<HashSet<String>>>,
+    /// Held while the index is written, so saves in the background don't interleave.
+    saving: Arc<Mutex<()>>,
+}
+
+impl AudioCache {
+    /// Open the cache directory named by `MUSE_CACHE_DIR`, or `None` if caching is off.
+    ///
+    /// Its size is limited to `MUSE_CACHE_SIZE` megabytes. Audio files that don't match the index,
+    /// such as interrupted downloads, are removed.
+    pub(crate) fn load() -> Result<Option<Self>> {
+        let Some(dir) = env::var_os("MUSE_CACHE_DIR").map(PathBuf::from) else {
+            return Ok(None);
+        };
+        let max_size = env::var("MUSE_CACHE_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_CACHE_SIZE_MB)
+            * 1024
+            * 1024;
+
+        fs::create_dir_all(&dir)?;
+        let mut songs: HashMap<String, CachedSong> = load_json(&dir.join(INDEX_FILE))?;
+        songs.retain(|id, song| file_size(&audio_path(&dir, id)) == Some(song.size));
+
+        for entry in fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            let extension = path.extension().and_then(|ext| ext.to_str());
+            let indexed = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|id| songs.contains_key(id));
+            // Interrupted downloads, and audio whose index entry was dropped.
+            if extension == Some("download") || (extension == Some("audio") && !indexed) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+
+        let cache = Self {
+            dir,
+            max_size,
+            songs: Arc::new(Mutex::new(songs)),
+            downloading: Arc::new(Mutex::new(HashSet::new())),
+            saving: Arc::new(Mutex::new(())),
+        };
+        cache.save(&cache.songs.lock().unwrap());
+        Ok(Some(cache))
+    }
+
+    fn save(&self, songs: &HashMap<String, CachedSong>) {
+        if let Err(e) = save_json(&self.dir.join(INDEX_FILE), songs) {
+            error!("Error while saving the audio cache index: {e}");
+        }
+    }
+
+    /// Save the index in the background, as it is by the time it's written.
+    fn save_soon(&self) {
+        let cache = self.clone();
+        task::spawn_blocking(move || {
+            let _saving = cache.saving.lock().unwrap();
+            let songs = cache.songs.lock().unwrap().clone();
+            cache.save(&songs);
+        });
+    }
+
+    /// The cached song for `url`, if there is one.
+    pub(crate) fn get(&self, url: &str) -> Option<CachedSong> {
+        self.songs.lock().unwrap().get(&video_id(url)?).cloned()
+    }
+
+    /// The audio file cached for `url`, to be played from `start`.
+    ///
+    /// Playing it from the beginning counts as a play, and checks the whole file is intact rather
+    /// than just its size. A damaged file is dropped from the cache instead.
+    pub(crate) async fn open(&self, url: &str, start: Option<Duration>) -> Option<PathBuf> {
+        let id = video_id(url)?;
+        let path = audio_path(&self.dir, &id);
+        let song = self.songs.lock().unwrap().get(&id).cloned()?;
+
+        let intact = if from_beginning(start) {
+            let hash = task::spawn_blocking({
+                let path = path.clone();
+                move || file_hash(&path)
+            })
+            .await;
+            matches!(hash, Ok(Ok(hash)) if hash == song.hash)
+        } else {
+            file_size(&path) == Some(song.size)
+        };
+        if !intact {
+            error!("Cached audio for `{url}` is damaged, so it will be downloaded again.");
+            self.songs.lock().unwrap().remove(&id);
+            let _ = fs::remove_file(&path);
+            self.save_soon();
+            return None;
+        }
+
+        if from_beginning(start) {
+            if let Some(song) = self.songs.lock().unwrap().get_mut(&id) {
+                song.plays += 1;
+                song.last_used = Utc::now();
+            }
+            self.save_soon();
+        }
+        Some(path)
+    }
+
+    /// Download `url` into the cache in the background, unless it's there already.
+    ///
+    /// Only songs of known length should be fetched, since a livestream would never finish.
+    pub(crate) fn fetch(&self, url: &str) {
+        let Some(id) = video_id(url) else {
+            return;
+        };
+        if self.songs.lock().unwrap().contains_key(&id)
+            || !self.downloading.lock().unwrap().insert(id.clone())
+        {
+            return;
+        }
+
+        let cache = self.clone();
+        let url = url.to_string();
+        tokio::spawn(async move {
+            match timeout(DOWNLOAD_TIMEOUT, cache.download(&id, &url)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Error while caching `{url}`: {e}"),
+                Err(_) => {
+                    error!("Gave up caching `{url}`, as it took too long.");
+                    let _ = fs::remove_file(cache.dir.join(format!("{id}.download")));
+                }
+            }
+            cache.downloading.lock().unwrap().remove(&id);
+        });
+    }
+
+    async fn download(&self, id: &str, url: &str) -> Result<()> {
+        let partial = self.dir.join(format!("{id}.download"));
+        let output = TokioCommand::new(YTDL_COMMAND)
+            .args([
+                "-j",
+                "--no-simulate",
+                "-f",
+                "webm[abr>0]/bestaudio",
+                "--no-playlist",
+                "--no-part",
+                "--match-filter",
+                "!is_live",
+                "--max-filesize",
+                &self.max_size.to_string(),
+                "--ignore-config",
+                "--no-warnings",
+                "-o",
+            ])
+            .arg(&partial)
+            .arg(url)
+            .stdin(Stdio::null())
+            // Timing out drops the download, which shouldn't leave yt-dlp running.
+            .kill_on_drop(true)
+            .output()
+            .await?;
+
+        let size = file_size(&partial).unwrap_or_default();
+        let json = output.stdout.split(|&b| b == b'\n').next();
+        let metadata = json
+            .and_then(|json| serde_json::from_slice(json).ok())
+            .map(Metadata::from_ytdl_output)
+            .filter(|_| output.status.success() && size > 0);
+        let Some(metadata) = metadata else {
+            let _ = fs::remove_file(&partial);
+            bail!(
+                "yt-dlp failed to download ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        };
+        // Songs of unknown length may be livestreams after all, and anything bigger than the cache
+        // can't fit.
+        if metadata.duration.is_none() || size > self.max_size {
+            let _ = fs::remove_file(&partial);
+            return Ok(());
+        }
+
+        let hash = task::spawn_blocking({
+            let partial = partial.clone();
+            move || file_hash(&partial)
+        })
+        .await??;
+        fs::rename(&partial, audio_path(&self.dir, id))?;
+        let song = CachedSong {
+            title: metadata.title.unwrap_or_else(|| url.to_string()),
+            url: metadata.source_url.unwrap_or_else(|| url.to_string()),
+            duration: metadata.duration,
+            author: metadata.artist.or(metadata.channel),
+            thumbnail: metadata.thumbnail,
+            size,
+            hash,
+            plays: 0,
+            last_used: Utc::now(),
+        };
+        debug!("Cached `{}` ({size} bytes).", song.title);
+
+        {
+            let mut songs = self.songs.lock().unwrap();
+            songs.insert(id.to_string(), song);
+            self.evict(&mut songs);
+        }
+        self.save_soon();
+        Ok(())
+    }
+
+    /// Remove the least recently played songs until the cache fits in its size limit.
+    fn evict(&self, songs: &mut HashMap<String, CachedSong>) {
+        let mut total: u64 = songs.values().map(|song| song.size).sum();
+        while total > self.max_size {
+            let Some(id) = songs
+                .iter()
+                .min_by_key(|(_, song)| song.last_used)
+                .map(|(id, _)| id.clone())
+            else {
+                break;
+            };
+            if let Some(song) = songs.remove(&id) {
+                debug!("Evicted `{}` from the audio cache.", song.title);
+                total -= song.size;
+            }
+            let _ = fs::remove_file(audio_path(&self.dir, &id));
+        }
+    }
+
+    /// Every cached song, and the cache's size limit in bytes.
+    pub(crate) fn songs(&self) -> (Vec<CachedSong>, u64) {
+        let songs = self.songs.lock().unwrap().values().cloned().collect();
+        (songs, self.max_size)
+    }
+
+    /// Delete every cached song, returning how many there were.
+    pub(crate) fn purge(&self) -> usize {
+        let count = {
+            let mut songs = self.songs.lock().unwrap();
+            for id in songs.keys() {
+                let _ = fs::remove_file(audio_path(&self.dir, id));
+            }
+            let count = songs.len();
+            songs.clear();
+            count
+        };
+        self.save_soon();
+        count
+    }
+}
+
+fn audio_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.audio"))
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|metadata| metadata.len())
+}
+
+/// The hex SHA-256 of the file at `path`.
+fn file_hash(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+
+    /// A cache in a fresh directory, holding `audio` for [`URL`].
+    fn cache_with(name: &str, audio: &[u8]) -> AudioCache {
+        let dir = env::temp_dir().join(format!("muse-cache-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let id = video_id(URL).unwrap();
+        let path = audio_path(&dir, &id);
+        fs::write(&path, audio).unwrap();
+        let song = CachedSong {
+            title: "Song".to_string(),
+            url: URL.to_string(),
+            duration: Some(Duration::from_secs(212)),
+            author: None,
+            thumbnail: None,
+            size: audio.len() as u64,
+            hash: file_hash(&path).unwrap(),
+            plays: 0,
+            last_used: Utc::now(),
+        };
+
+        AudioCache {
+            dir,
+            max_size: 1024,
+            songs: Arc::new(Mutex::new(HashMap::from([(id, song)]))),
+            downloading: Arc::new(Mutex::new(HashSet::new())),
+            saving: Arc::new(Mutex::new(())),
+        }
+    }
+
+    #[test]
+    fn finds_video_ids() {
+        let id = Some("youtube-dQw4w9WgXcQ".to_string());
+        assert_eq!(video_id(URL), id);
+        assert_eq!(video_id("https://youtu.be/dQw4w9WgXcQ?t=1"), id);
+        assert_eq!(video_id("https://youtube.com/shorts/dQw4w9WgXcQ"), id);
+        assert_eq!(
+            video_id("https://music.youtube.com/watch?list=x&v=dQw4w9WgXcQ"),
+            id
+        );
+        assert_eq!(video_id("https://youtube.com/watch?v=short"), None);
+        assert_eq!(video_id("https://example.com/watch?v=dQw4w9WgXcQ"), None);
+    }
+
+    #[tokio::test]
+    async fn only_plays_from_the_start_count() {
+        let cache = cache_with("plays", b"audio");
+        assert!(cache.open(URL, Some(Duration::ZERO)).await.is_some());
+        assert!(cache
+            .open(URL, Some(Duration::from_secs(30)))
+            .await
+            .is_some());
+        assert_eq!(cache.get(URL).unwrap().plays, 1);
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+
+    #[tokio::test]
+    async fn damaged_audio_is_dropped() {
+        let cache = cache_with("damaged", b"audio");
+        let path = cache.open(URL, None).await.unwrap();
+        // Same size, different contents.
+        fs::write(&path, b"AUDIO").unwrap();
+
+        // Seeking only checks the size, but playing from the start notices.
+        assert!(cache
+            .open(URL, Some(Duration::from_secs(30)))
+            .await
+            .is_some());
+        assert!(cache.open(URL, None).await.is_none());
+        assert!(cache.get(URL).is_none());
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(&cache.dir);
+    }
+}
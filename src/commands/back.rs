@@ -0,0 +1,153 @@
+use anyhow::anyhow;
+use log::debug;
+use poise::command;
+
+use crate::{
+    filters::GuildFilters,
+    format::{format_duration, song_embed},
+    local::song_input,
+    permissions::is_dj,
+    snapshot::snapshot_queue,
+    track::{requester, TrackInfo},
+    types::*,
+};
+
+/// Play the previous song again.
+#[command(slash_command, guild_only)]
+pub(crate) async fn back(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = ctx.data().settings.get(guild_id);
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let Some(handler_lock) = manager.get(guild_id) else {
+        ctx.send(|m| m.content("I'm not in a voice channel.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let queue = handler_lock.lock().await.queue().clone();
+
+    // Going back interrupts the current song, so it's limited like skipping it.
+    if let Some(np) = queue.current() {
+        let is_dj = match ctx.author_member().await {
+            Some(member) => is_dj(
+                ctx.serenity_context().cache.as_ref(),
+                guild_id,
+                &member,
+                settings.dj_role,
+            ),
+            None => false,
+        };
+
+        if !is_dj && requester(&np) != Some(ctx.author().id) {
+            ctx.send(|m| {
+                m.content("Only DJs and whoever requested the current song can go back.")
+                    .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
+        }
+    }
+
+    let Some(previous) = ctx
+        .data()
+        .guilds
+        .with(guild_id, |g| g.history.front().cloned())
+    else {
+        ctx.send(|m| m.content("No songs have been played yet.").ephemeral(true))
+            .await?;
+        return Ok(());
+    };
+
+    // Checked again once it's resolved, in case the queue filled up in the meantime.
+    if settings.queue_space(queue.len()) == 0 {
+        ctx.send(|m| m.content("The queue is full.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let reply_handle = ctx
+        .say(format!("Going back to *{}*…", previous.title))
+        .await?;
+
+    debug!(
+        "Going back to `{}` in {}.",
+        previous.url,
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string())
+    );
+
+    let mut turn = ctx.data().guilds.with(guild_id, |g| g.enqueue_turn());
+    let filters = GuildFilters::new(ctx.data(), guild_id);
+    let song = song_input(previous.url.clone(), filters).await?;
+    if !settings.allows_duration(song.metadata.duration.as_ref()) {
+        let max = format_duration(&settings.max_track_duration().unwrap());
+        reply_handle.delete(ctx).await?;
+        ctx.send(|m| {
+            m.content(format!("Songs can't be longer than `{max}`."))
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    turn.wait().await;
+    if settings.queue_space(handler_lock.lock().await.queue().len()) == 0 {
+        reply_handle.delete(ctx).await?;
+        ctx.send(|m| m.content("The queue is full.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+    let (track, handle) = ctx.data().guilds.with(guild_id, |g| {
+        g.create_track(song, previous.requester.or(Some(ctx.author().id)))
+    });
+
+    {
+        let mut handler = handler_lock.lock().await;
+        handler.enqueue(track);
+    }
+    // Only forgotten once it's queued again, so a song that fails to resolve can still be
+    // gone back to. Songs may have finished since, so it's found rather than assumed to be first.
+    ctx.data().guilds.with(guild_id, |g| {
+        let position = g
+            .history
+            .iter()
+            .position(|song| song.url == previous.url && song.played_at == previous.played_at);
+        if let Some(position) = position {
+            g.history.remove(position);
+        }
+    });
+
+    // Pause the current song behind the previous one so it resumes once that finishes.
+    if queue.len() > 1 {
+        if let Some(np) = queue.current() {
+            np.pause()?;
+        }
+        queue.modify_queue(|q| {
+            if let Some(track) = q.pop_back() {
+                q.push_front(track);
+            }
+        });
+        handle.play()?;
+    }
+    snapshot_queue(ctx, &handler_lock).await;
+    drop(turn);
+
+    let volume = ctx.data().guilds.with(guild_id, |g| g.volume);
+    reply_handle
+        .edit(ctx, |m| {
+            m.content(format!("Going back to *{}*.", previous.title))
+                .embed(|e| {
+                    song_embed(
+                        e,
+                        settings.embed_colour(),
+                        &TrackInfo::of(&handle),
+                        requester(&handle),
+                        volume,
+                    )
+                })
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use log::debug;
+use poise::command;
+
+use crate::{format::cache_embed, settings::DEFAULT_EMBED_COLOUR, types::*};
+
+/// Inspect or purge the audio cache.
+#[command(slash_command, owners_only, subcommands("cache_view", "cache_purge"))]
+pub(crate) async fn cache(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Show what's in the audio cache.
+#[command(slash_command, owners_only, rename = "view")]
+pub(crate) async fn cache_view(ctx: Context<'_>) -> Result<()> {
+    let Some(cache) = &ctx.data().cache else {
+        ctx.send(|m| m.content("The audio cache is disabled.").ephemeral(true))
+            .await?;
+        return Ok(());
+    };
+
+    let colour = ctx.guild_id().map_or(DEFAULT_EMBED_COLOUR, |guild_id| {
+        ctx.data().settings.get(guild_id).embed_colour()
+    });
+    let (songs, max_size) = cache.songs();
+    ctx.send(|m| {
+        m.embed(|e| cache_embed(e, colour, &songs, max_size))
+            .ephemeral(true)
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Delete every song in the audio cache.
+#[command(slash_command, owners_only, rename = "purge")]
+pub(crate) async fn cache_purge(ctx: Context<'_>) -> Result<()> {
+    let Some(cache) = &ctx.data().cache else {
+        ctx.send(|m| m.content("The audio cache is disabled.").ephemeral(true))
+            .await?;
+        return Ok(());
+    };
+
+    let count = cache.purge();
+    ctx.send(|m| {
+        m.content(format!("Purged {count} songs from the audio cache."))
+            .ephemeral(true)
+    })
+    .await?;
+    debug!("Purged {count} songs from the audio cache.");
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use std::{mem, time::Duration};
+
+use anyhow::anyhow;
+use log::{debug, error};
+use poise::command;
+
+use crate::{
+    filters::{parse_equalizer, Filters, Preset, MAX_EQ_GAIN},
+    track::seek_anchor,
+    types::*,
+};
+
+/// How far back the current song restarts when the filters change, so it isn't skipped forward.
+const RESTART_REWIND: Duration = Duration::from_millis(100);
+
+/// Change the audio effects applied to every song.
+#[command(
+    slash_command,
+    guild_only,
+    subcommands("filter_set", "filter_preset", "filter_clear")
+)]
+pub(crate) async fn filter(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Turn individual filters on or off.
+#[command(slash_command, guild_only, rename = "set")]
+pub(crate) async fn filter_set(
+    ctx: Context<'_>,
+    #[description = "Speed percentage, without changing pitch (50-200)."]
+    #[min = 50]
+    #[max = 200]
+    speed: Option<u16>,
+    #[description = "Pitch percentage, without changing speed (50-200)."]
+    #[min = 50]
+    #[max = 200]
+    pitch: Option<u16>,
+    #[description = "Bass boost in dB (0-20)."]
+    #[min = 0]
+    #[max = 20]
+    bass_boost: Option<u8>,
+    #[description = "Ten band gains in dB from 31 Hz to 16 kHz, e.g. 3 2 0 0 0 0 0 1 2 3."]
+    equalizer: Option<String>,
+    #[description = "Reduce the vocals."] karaoke: Option<bool>,
+    #[description = "Pan the sound around you."] eight_d: Option<bool>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let equalizer = match equalizer.as_deref().map(parse_equalizer) {
+        Some(Some(gains)) => Some(gains),
+        Some(None) => {
+            ctx.send(|m| {
+                m.content(format!(
+                    "The equalizer needs ten gains between -{MAX_EQ_GAIN} and {MAX_EQ_GAIN} dB."
+                ))
+                .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
+        }
+        None => None,
+    };
+
+    if speed.is_none()
+        && pitch.is_none()
+        && bass_boost.is_none()
+        && equalizer.is_none()
+        && karaoke.is_none()
+        && eight_d.is_none()
+    {
+        ctx.send(|m| {
+            m.content("Specify at least one filter to change.")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let (previous_speed, filters) = ctx.data().guilds.with(guild_id, |g| {
+        let previous_speed = g.filters.speed_factor();
+        let filters = &mut g.filters;
+        if let Some(speed) = speed {
+            filters.speed = Some(speed.clamp(50, 200));
+        }
+        if let Some(pitch) = pitch {
+            filters.pitch = Some(pitch.clamp(50, 200));
+        }
+        if let Some(gain) = bass_boost {
+            filters.bass_boost = Some(gain.min(20));
+        }
+        if let Some(gains) = equalizer {
+            // A flat equalizer is the same as none.
+            filters.equalizer = gains.iter().any(|&gain| gain != 0).then_some(gains);
+        }
+        if let Some(karaoke) = karaoke {
+            filters.karaoke = karaoke;
+        }
+        if let Some(eight_d) = eight_d {
+            filters.eight_d = eight_d;
+        }
+        (previous_speed, filters.clone())
+    });
+
+    apply_filters(ctx, previous_speed, &filters).await
+}
+
+/// Use a ready-made combination of filters.
+#[command(slash_command, guild_only, rename = "preset")]
+pub(crate) async fn filter_preset(
+    ctx: Context<'_>,
+    #[description = "The preset to use."] preset: Preset,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let filters = preset.filters();
+    let previous_speed = ctx.data().guilds.with(guild_id, |g| {
+        mem::replace(&mut g.filters, filters.clone()).speed_factor()
+    });
+
+    apply_filters(ctx, previous_speed, &filters).await
+}
+
+/// Turn off every filter.
+#[command(slash_command, guild_only, rename = "clear")]
+pub(crate) async fn filter_clear(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let previous_speed = ctx
+        .data()
+        .guilds
+        .with(guild_id, |g| mem::take(&mut g.filters).speed_factor());
+
+    apply_filters(ctx, previous_speed, &Filters::default()).await
+}
+
+/// Restart the current song where it is so the new filters are heard, then report them.
+///
+/// `previous_speed` is the speed factor the song was playing at before the filters changed.
+async fn apply_filters(ctx: Context<'_>, previous_speed: f64, filters: &Filters) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let np = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.queue().current(),
+        None => None,
+    };
+    if let Some(np) = np {
+        let position = np.get_info().await?.position;
+        let position = seek_anchor(&np, previous_speed)
+            .song_position(position)
+            .saturating_sub(RESTART_REWIND);
+        // A song that hasn't started yet will start with the new filters anyway.
+        if let Err(e) = ctx
+            .data()
+            .guilds
+            .restart_track(guild_id, &np, position)
+            .await
+        {
+            error!("Error while restarting track with new filters: {e}");
+        }
+    }
+
+    match filters.describe() {
+        Some(description) => {
+            ctx.say(format!("Set the filters to {description}."))
+                .await?
+        }
+        None => ctx.say("Turned off the filters.").await?,
+    };
+    debug!(
+        "Set filters to {filters:?} in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use log::{error, warn};
+use poise::{
+    command,
+    serenity_prelude::{CollectComponentInteraction, InteractionResponseType},
+};
+
+use crate::{
+    format::{history_message, history_message_edit, turn_page},
+    types::*,
+};
+
+/// View recently played songs.
+#[command(slash_command, guild_only)]
+pub(crate) async fn history(
+    ctx: Context<'_>,
+    #[description = "History page"] page: Option<usize>,
+) -> Result<()> {
+    let mut page = page.unwrap_or(0);
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = ctx.data().settings.get(guild_id);
+    let history: Vec<_> = ctx
+        .data()
+        .guilds
+        .with(guild_id, |g| g.history.iter().cloned().collect());
+
+    let reply_handle = if history.is_empty() {
+        ctx.send(|m| m.content("No songs have been played yet.").ephemeral(true))
+            .await?;
+        return Ok(());
+    } else {
+        ctx.send(|m| {
+            let (m, new_page) = history_message(m, &settings, &history, page, false);
+            page = new_page;
+            m
+        })
+        .await?
+    };
+
+    while let Some(interaction) = CollectComponentInteraction::new(ctx)
+        .author_id(ctx.author().id)
+        .message_id(reply_handle.message().await?.id)
+        .timeout(Duration::from_secs(60))
+        .await
+    {
+        let Some(new_page) = turn_page(&interaction.data.custom_id, page) else {
+            warn!("Unknown interaction `{}`,", interaction.data.custom_id);
+            continue;
+        };
+        page = new_page;
+
+        let mut msg = interaction.message.clone();
+        msg.edit(ctx, |m| {
+            let (m, new_page) = history_message_edit(m, &settings, &history, page);
+            page = new_page;
+            m
+        })
+        .await?;
+
+        if let Err(e) = interaction
+            .create_interaction_response(ctx, |r| {
+                r.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await
+        {
+            error!("Error while creating interaction response for history: {e}");
+        };
+    }
+
+    reply_handle
+        .edit(ctx, |m| {
+            history_message(m, &settings, &history, page, true).0
+        })
+        .await?;
+
+    Ok(())
+}
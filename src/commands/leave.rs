@@ -2,7 +2,7 @@ use anyhow::anyhow;
 use log::debug;
 use poise::command;
 
-use crate::types::*;
+use crate::{controls::disable_panel, types::*};
 
 /// Leave the voice channel.
 #[command(slash_command, guild_only)]
@@ -14,6 +14,9 @@ pub(crate) async fn leave(ctx: Context<'_>) -> Result<()> {
 
     let handler = manager.get(guild_id);
     if handler.is_some() {
+        ctx.data().snapshots.set(guild_id, None);
+        disable_panel(&ctx.serenity_context().http, &ctx.data().guilds, guild_id).await;
+        ctx.data().guilds.with(guild_id, |g| g.prefetched = None);
         manager.remove(guild_id).await?;
         ctx.say("Left voice channel.").await?;
         debug!(
@@ -0,0 +1,55 @@
+use anyhow::anyhow;
+use log::{debug, error};
+use poise::command;
+use songbird::tracks::TrackQueue;
+
+use crate::{state::LoopMode, types::*};
+
+/// Set how the queue repeats.
+#[command(slash_command, guild_only, rename = "loop")]
+pub(crate) async fn loop_mode(
+    ctx: Context<'_>,
+    #[description = "Loop mode"] mode: LoopMode,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    ctx.data().guilds.with(guild_id, |g| g.loop_mode = mode);
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let handler = handler_lock.lock().await;
+        loop_tracks(handler.queue(), mode);
+    }
+
+    ctx.say(loop_message(mode)).await?;
+    debug!(
+        "Set loop mode to {mode} in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    Ok(())
+}
+
+/// Make every track in `queue` loop on its own if `mode` is [`LoopMode::Track`].
+pub(crate) fn loop_tracks(queue: &TrackQueue, mode: LoopMode) {
+    for track in queue.current_queue() {
+        let res = if mode == LoopMode::Track {
+            track.enable_loop()
+        } else {
+            track.disable_loop()
+        };
+        if let Err(e) = res {
+            error!("Error while setting track loop: {e}");
+        }
+    }
+}
+
+pub(crate) fn loop_message(mode: LoopMode) -> &'static str {
+    match mode {
+        LoopMode::Off => "Stopped looping.",
+        LoopMode::Track => "Looping the current song.",
+        LoopMode::Queue => "Looping the queue.",
+    }
+}
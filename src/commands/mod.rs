@@ -1,15 +1,45 @@
+pub(crate) mod back;
+pub(crate) mod cache;
+pub(crate) mod filter;
+pub(crate) mod history;
 pub(crate) mod leave;
+pub(crate) mod loop_mode;
+pub(crate) mod move_song;
 pub(crate) mod now_playing;
+pub(crate) mod pause;
 pub(crate) mod play;
+pub(crate) mod playlist;
 pub(crate) mod queue;
 pub(crate) mod register;
 pub(crate) mod remove;
+pub(crate) mod resume;
+pub(crate) mod search;
+pub(crate) mod seek;
+pub(crate) mod settings;
+pub(crate) mod shuffle;
 pub(crate) mod skip;
+pub(crate) mod swap;
+pub(crate) mod volume;
 
+pub(crate) use back::back;
+pub(crate) use cache::cache;
+pub(crate) use filter::filter;
+pub(crate) use history::history;
 pub(crate) use leave::leave;
+pub(crate) use loop_mode::loop_mode;
+pub(crate) use move_song::move_song;
 pub(crate) use now_playing::now_playing;
+pub(crate) use pause::pause;
 pub(crate) use play::play;
+pub(crate) use playlist::playlist;
 pub(crate) use queue::queue;
 pub(crate) use register::register;
 pub(crate) use remove::remove;
+pub(crate) use resume::resume;
+pub(crate) use search::search;
+pub(crate) use seek::seek;
+pub(crate) use settings::settings;
+pub(crate) use shuffle::shuffle;
 pub(crate) use skip::skip;
+pub(crate) use swap::swap;
+pub(crate) use volume::volume;
@@ -0,0 +1,54 @@
+use anyhow::anyhow;
+use poise::command;
+
+use crate::{snapshot::snapshot_queue, track::TrackInfo, types::*};
+
+/// Move a song to a different position in the queue.
+#[command(slash_command, guild_only, rename = "move")]
+pub(crate) async fn move_song(
+    ctx: Context<'_>,
+    #[description = "Song number (based on queue) to move"] from: usize,
+    #[description = "Song number (based on queue) to move it to"] to: usize,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let Some(handler_lock) = manager.get(guild_id) else {
+        ctx.send(|m| m.content("I'm not in a voice channel.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let song = {
+        let handler = handler_lock.lock().await;
+        let queue = handler.queue();
+        if queue.is_empty() {
+            ctx.send(|m| m.content("I'm not playing any songs.").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+        // The currently playing song can't be moved, nor can anything take its place.
+        if from == 0 || to == 0 || from >= queue.len() || to >= queue.len() {
+            ctx.send(|m| m.content("Invalid song number.").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+
+        queue.modify_queue(|q| {
+            let song = q.remove(from).unwrap();
+            let handle = song.handle();
+            q.insert(to, song);
+            handle
+        })
+    };
+    snapshot_queue(ctx, &handler_lock).await;
+
+    ctx.say(format!(
+        "Moved *{}* to position {to}.",
+        TrackInfo::of(&song).title
+    ))
+    .await?;
+
+    Ok(())
+}
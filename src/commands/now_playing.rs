@@ -1,12 +1,66 @@
+use std::time::Duration;
+
 use anyhow::anyhow;
-use poise::command;
+use log::{debug, error};
+use poise::{command, serenity_prelude::GuildId};
+use songbird::tracks::{TrackHandle, TrackResult, TrackState};
+use tokio::time::{sleep, Instant};
+
+use crate::{
+    format::now_playing_message,
+    state::GuildStore,
+    track::{requester, seek_anchor, TrackInfo},
+    types::*,
+};
+
+/// How often live `/nowplaying` replies are updated.
+const LIVE_UPDATE_PERIOD: Duration = Duration::from_secs(10);
+/// Interaction replies can only be edited for 15 minutes.
+const LIVE_UPDATE_LIMIT: Duration = Duration::from_secs(14 * 60);
+/// Most live `/nowplaying` replies per guild, to stay clear of rate limits.
+const MAX_LIVE_MESSAGES: usize = 2;
+
+/// One of a guild's live `/nowplaying` slots, given back when dropped.
+struct LiveSlot {
+    guilds: GuildStore,
+    guild_id: GuildId,
+}
+
+impl LiveSlot {
+    fn acquire(guilds: &GuildStore, guild_id: GuildId) -> Option<Self> {
+        let acquired = guilds.with(guild_id, |g| {
+            if g.live_now_playing >= MAX_LIVE_MESSAGES {
+                return false;
+            }
+            g.live_now_playing += 1;
+            true
+        });
+
+        acquired.then(|| Self {
+            guilds: guilds.clone(),
+            guild_id,
+        })
+    }
+}
+
+impl Drop for LiveSlot {
+    fn drop(&mut self) {
+        self.guilds.with(self.guild_id, |g| g.live_now_playing -= 1);
+    }
+}
 
-use crate::{format::now_playing_message, types::*};
+/// `np`'s state, with its position in the song rather than in the audio played at `speed`.
+async fn song_info(np: &TrackHandle, speed: f64) -> TrackResult<TrackState> {
+    let mut info = np.get_info().await?;
+    info.position = seek_anchor(np, speed).song_position(info.position);
+    Ok(info)
+}
 
 /// View the currently playing song.
 #[command(slash_command, guild_only, rename = "nowplaying")]
 pub(crate) async fn now_playing(ctx: Context<'_>) -> Result<()> {
     let guild_id = ctx.guild_id().unwrap();
+    let colour = ctx.data().settings.get(guild_id).embed_colour();
     let Some(manager) = songbird::get(ctx.serenity_context()).await else {
         return Err(anyhow!(SONGBIRD_MANAGER_ERR));
     };
@@ -16,9 +70,9 @@ pub(crate) async fn now_playing(ctx: Context<'_>) -> Result<()> {
         return Ok(());
     };
 
-    let np = {
+    let (queue, np) = {
         let handler = handler_lock.lock().await;
-        handler.queue().current()
+        (handler.queue().clone(), handler.queue().current())
     };
 
     let Some(np) = np else {
@@ -26,6 +80,46 @@ pub(crate) async fn now_playing(ctx: Context<'_>) -> Result<()> {
         return Ok(());
     };
 
-    ctx.send(|m| now_playing_message(m, np.metadata())).await?;
+    let song = TrackInfo::of(&np);
+    let filters = ctx.data().guilds.with(guild_id, |g| g.filters.clone());
+    let info = song_info(&np, filters.speed_factor()).await?;
+
+    let reply_handle = ctx
+        .send(|m| now_playing_message(m, colour, &song, requester(&np), &info, &filters))
+        .await?;
+
+    let Some(_slot) = LiveSlot::acquire(&ctx.data().guilds, guild_id) else {
+        return Ok(());
+    };
+    debug!(
+        "Updating now playing live in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string())
+    );
+
+    // Keep the progress bar moving until the song changes.
+    let deadline = Instant::now() + LIVE_UPDATE_LIMIT;
+    loop {
+        sleep(LIVE_UPDATE_PERIOD).await;
+        if Instant::now() >= deadline
+            || queue.current().map(|track| track.uuid()) != Some(np.uuid())
+        {
+            break;
+        }
+        let filters = ctx.data().guilds.with(guild_id, |g| g.filters.clone());
+        let Ok(info) = song_info(&np, filters.speed_factor()).await else {
+            break;
+        };
+
+        if let Err(e) = reply_handle
+            .edit(ctx, |m| {
+                now_playing_message(m, colour, &song, requester(&np), &info, &filters)
+            })
+            .await
+        {
+            error!("Error while updating now playing: {e}");
+            break;
+        }
+    }
+
     Ok(())
 }
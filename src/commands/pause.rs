@@ -0,0 +1,50 @@
+use anyhow::anyhow;
+use log::debug;
+use poise::command;
+use songbird::tracks::PlayMode;
+
+use crate::{track::TrackInfo, types::*};
+
+/// Pause the current song.
+#[command(slash_command, guild_only)]
+pub(crate) async fn pause(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let Some(handler_lock) = manager.get(guild_id) else {
+        ctx.send(|m| m.content("I'm not in a voice channel.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let np = {
+        let handler = handler_lock.lock().await;
+        handler.queue().current()
+    };
+
+    let Some(np) = np else {
+        ctx.send(|m| m.content("I'm not playing a song.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    if np.get_info().await?.playing == PlayMode::Pause {
+        ctx.send(|m| m.content("The song is already paused.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    {
+        let handler = handler_lock.lock().await;
+        handler.queue().pause()?;
+    }
+
+    let title = TrackInfo::of(&np).title;
+    ctx.say(format!("Paused *{title}*.")).await?;
+    debug!(
+        "Paused `{title}` in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    Ok(())
+}
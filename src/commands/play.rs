@@ -1,32 +1,436 @@
+use std::{cmp::Reverse, collections::HashSet, path::PathBuf, sync::Arc};
+
 use anyhow::anyhow;
-use log::{debug, trace};
+use log::{debug, error, trace};
 use poise::{
     command,
-    serenity_prelude::{ChannelType, GuildChannel, Mentionable},
-};
-use songbird::{
-    input::{Input, Restartable},
-    Event, TrackEvent,
+    serenity_prelude::{Attachment, ChannelType, GuildChannel, Mentionable},
+    AutocompleteChoice, ReplyHandle,
 };
+use rand::{seq::SliceRandom, thread_rng};
+use songbird::{input::Input, Call};
+use tokio::{sync::Mutex, task};
 
 use crate::{
-    event::NowPlaying,
-    format::{format_user_for_log, song_embed},
+    event::add_call_events,
+    filters::GuildFilters,
+    format::{format_duration, format_user_for_log, playlist_embed, song_embed, truncate},
+    fuzzy::fuzzy_score,
+    local::{
+        attachment_input, file_input, is_audio_attachment, is_file_url, lazy_song_input,
+        list_files, music_dir, resolve_in, song_input,
+    },
+    snapshot::snapshot_queue,
+    state::EnqueueTurn,
+    track::TrackInfo,
     types::*,
+    ytdl::{enumerate_playlist, is_playlist_url, is_url, ytdl_input, Playlist},
 };
 
+/// Suggest recently requested and queued songs matching what has been typed so far.
+///
+/// This must not touch the network, since Discord only waits 3 seconds for suggestions.
+async fn autocomplete_song(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice<String>> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![];
+    };
+
+    let mut candidates: Vec<(String, String)> = ctx.data().guilds.with(guild_id, |g| {
+        g.recent_songs
+            .iter()
+            .map(|song| (song.title.clone(), song.url.clone()))
+            .collect()
+    });
+
+    if let Some(handler_lock) = songbird::get(ctx.serenity_context())
+        .await
+        .and_then(|manager| manager.get(guild_id))
+    {
+        let queue = handler_lock.lock().await.queue().current_queue();
+        candidates.extend(queue.iter().filter_map(|track| {
+            let metadata = track.metadata();
+            Some((metadata.title.clone()?, metadata.source_url.clone()?))
+        }));
+    }
+
+    let mut seen = HashSet::new();
+    let mut matches: Vec<_> = candidates
+        .into_iter()
+        // Discord rejects choice values longer than 100 characters.
+        .filter(|(_, url)| url.len() <= 100)
+        .filter(|(_, url)| seen.insert(url.clone()))
+        .filter_map(|(title, url)| Some((fuzzy_score(partial, &title)?, title, url)))
+        .collect();
+    // Stable, so equally good matches stay most recent first.
+    matches.sort_by_key(|(score, _, _)| Reverse(*score));
+
+    matches
+        .into_iter()
+        .take(25)
+        .map(|(_, title, url)| AutocompleteChoice {
+            name: truncate(&title, 100),
+            value: url,
+        })
+        .collect()
+}
+
+/// Suggest files from the music library matching what has been typed so far.
+async fn autocomplete_file(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(music_dir) = music_dir() else {
+        return vec![];
+    };
+
+    // Walking a big library takes a while.
+    let files = task::spawn_blocking(move || list_files(&music_dir))
+        .await
+        .unwrap_or_default();
+    let mut matches: Vec<_> = files
+        .into_iter()
+        // Discord rejects choice values longer than 100 characters.
+        .filter(|file| file.len() <= 100)
+        .filter_map(|file| Some((fuzzy_score(partial, &file)?, file)))
+        .collect();
+    matches.sort_by_key(|(score, _)| Reverse(*score));
+
+    matches.into_iter().take(25).map(|(_, file)| file).collect()
+}
+
+enum Source {
+    Query(String),
+    File(PathBuf),
+    Attachment(Attachment),
+}
+
+impl Source {
+    /// What was asked for, to show while it's being resolved.
+    fn describe(&self) -> String {
+        match self {
+            Self::Query(query) => query.clone(),
+            Self::File(path) => path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+            Self::Attachment(attachment) => attachment.filename.clone(),
+        }
+    }
+
+    /// The message shown if this couldn't be resolved.
+    fn error_message(&self) -> String {
+        match self {
+            Self::Query(url) if is_url(url) || is_file_url(url) => {
+                format!("Couldn't load *{url}*.")
+            }
+            Self::Query(query) => format!("Couldn't find a song matching *{query}*."),
+            source => format!("Couldn't read *{}*.", source.describe()),
+        }
+    }
+
+    async fn resolve(self, filters: GuildFilters) -> Result<Input> {
+        match self {
+            Self::Query(url) if is_url(&url) || is_file_url(&url) => song_input(url, filters).await,
+            Self::Query(query) => Ok(ytdl_input(format!("ytsearch1:{query}"), filters).await?),
+            Self::File(path) => file_input(&path, filters).await,
+            Self::Attachment(attachment) => attachment_input(&attachment, filters).await,
+        }
+    }
+}
+
 /// Add a song to the queue.
 #[command(slash_command, guild_only)]
 pub(crate) async fn play(
     ctx: Context<'_>,
-    #[description = "The song to play (YouTube search or URL)."] song: String,
+    #[description = "The song to play (YouTube search or URL)."]
+    #[autocomplete = "autocomplete_song"]
+    song: Option<String>,
+    #[description = "A file from the music library to play."]
+    #[autocomplete = "autocomplete_file"]
+    file: Option<String>,
+    #[description = "An audio file to play."] attachment: Option<Attachment>,
     #[description = "The voice channel to join."] voice_channel: Option<GuildChannel>,
+    #[description = "Maximum number of songs to queue from a playlist."]
+    #[min = 1]
+    limit: Option<usize>,
+    #[description = "Shuffle the songs queued from a playlist."] shuffle: Option<bool>,
 ) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let guild_name = guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string());
+    let settings = ctx.data().settings.get(guild_id);
+
+    let source = match (song, file, attachment) {
+        (Some(song), None, None) => Source::Query(song),
+        (None, Some(file), None) => {
+            let Some(music_dir) = music_dir() else {
+                ctx.send(|m| m.content("Playing local files is disabled.").ephemeral(true)).await?;
+                return Ok(());
+            };
+            let Some(path) = resolve_in(&music_dir, &file) else {
+                ctx.send(|m| m.content("That file doesn't exist.").ephemeral(true)).await?;
+                return Ok(());
+            };
+            Source::File(path)
+        }
+        (None, None, Some(attachment)) => {
+            if !is_audio_attachment(&attachment) {
+                ctx.send(|m| m.content("That isn't an audio file.").ephemeral(true))
+                    .await?;
+                return Ok(());
+            }
+            Source::Attachment(attachment)
+        }
+        _ => {
+            ctx.send(|m| {
+                m.content("Specify exactly one song, file or attachment.")
+                    .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let Some((handler_lock, first_play)) = join_voice_channel(ctx, voice_channel).await? else {
+        return Ok(());
+    };
+
+    // Taken now, so songs resolved at the same time are queued in the order they were asked for.
+    let mut turn = ctx.data().guilds.with(guild_id, |g| g.enqueue_turn());
+
+    match &source {
+        Source::Query(song) if is_playlist_url(song) => {
+            // Not deferred, so errors found once it's enumerated can still be shown only to the
+            // author in place of this.
+            let reply_handle = ctx.say(format!("Resolving *{song}*…")).await?;
+            return enqueue_playlist(
+                ctx,
+                &handler_lock,
+                reply_handle,
+                song,
+                limit,
+                shuffle.unwrap_or(false),
+                turn,
+            )
+            .await;
+        }
+        Source::Query(song) => trace!(
+            "{} ran a YouTube search for `{}`.",
+            format_user_for_log(ctx.author()),
+            song
+        ),
+        Source::File(path) => trace!(
+            "{} played the local file `{}`.",
+            format_user_for_log(ctx.author()),
+            path.display()
+        ),
+        Source::Attachment(attachment) => trace!(
+            "{} played the attachment `{}`.",
+            format_user_for_log(ctx.author()),
+            attachment.filename
+        ),
+    }
+
+    // Checked again once it's resolved, in case the queue filled up in the meantime.
+    if settings.queue_space(handler_lock.lock().await.queue().len()) == 0 {
+        ctx.send(|m| m.content("The queue is full.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let reply_handle = ctx
+        .say(format!("Resolving *{}*…", source.describe()))
+        .await?;
+
+    let error_message = source.error_message();
+    let filters = GuildFilters::new(ctx.data(), guild_id);
+    let song = match source.resolve(filters).await {
+        Ok(song) => song,
+        Err(e) => {
+            error!("Error while resolving a song in {guild_name}: {e}");
+            reply_handle.edit(ctx, |m| m.content(error_message)).await?;
+            return Ok(());
+        }
+    };
+    if !settings.allows_duration(song.metadata.duration.as_ref()) {
+        let max = format_duration(&settings.max_track_duration().unwrap());
+        reply_handle.delete(ctx).await?;
+        ctx.send(|m| {
+            m.content(format!("Songs can't be longer than `{max}`."))
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    turn.wait().await;
+    if settings.queue_space(handler_lock.lock().await.queue().len()) == 0 {
+        reply_handle.delete(ctx).await?;
+        ctx.send(|m| m.content("The queue is full.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let info = TrackInfo::from(&*song.metadata);
+    let title = &info.title;
+    let volume = ctx.data().guilds.with(guild_id, |g| {
+        if let Some(url) = &info.url {
+            g.remember_song(title, url);
+        }
+        g.volume
+    });
+
+    {
+        let (track, _) = ctx
+            .data()
+            .guilds
+            .with(guild_id, |g| g.create_track(song, Some(ctx.author().id)));
+
+        let mut handler = handler_lock.lock().await;
+        handler.enqueue(track);
+    }
+    snapshot_queue(ctx, &handler_lock).await;
+    drop(turn);
+
+    debug!("Enqueued `{title}` in {guild_name}.");
+
+    reply_handle
+        .edit(ctx, |m| {
+            m.content(if first_play {
+                format!("Now playing *{title}*.")
+            } else {
+                format!("Queued *{title}*.")
+            })
+            .embed(|e| {
+                song_embed(
+                    e,
+                    settings.embed_colour(),
+                    &info,
+                    Some(ctx.author().id),
+                    volume,
+                )
+            })
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn enqueue_playlist(
+    ctx: Context<'_>,
+    handler_lock: &Mutex<Call>,
+    reply_handle: ReplyHandle<'_>,
+    url: &str,
+    limit: Option<usize>,
+    shuffle: bool,
+    mut turn: EnqueueTurn,
+) -> Result<()> {
+    trace!(
+        "{} enqueued the playlist `{}`.",
+        format_user_for_log(ctx.author()),
+        url
+    );
+
+    let playlist = enumerate_playlist(url).await?;
+    turn.wait().await;
+    enqueue_entries(ctx, handler_lock, reply_handle, playlist, limit, shuffle).await
+}
+
+/// Queue the songs in `playlist`, keeping to the guild's queue length and song duration limits.
+///
+/// `reply_handle` is edited to say what was queued, or replaced with an ephemeral error.
+pub(crate) async fn enqueue_entries(
+    ctx: Context<'_>,
+    handler_lock: &Mutex<Call>,
+    reply_handle: ReplyHandle<'_>,
+    mut playlist: Playlist,
+    limit: Option<usize>,
+    shuffle: bool,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = ctx.data().settings.get(guild_id);
+
+    playlist
+        .entries
+        .retain(|entry| settings.allows_duration(entry.duration.as_ref()));
+    if let Some(limit) = limit {
+        playlist.entries.truncate(limit);
+    }
+    if shuffle {
+        playlist.entries.shuffle(&mut thread_rng());
+    }
+
+    if playlist.entries.is_empty() {
+        reply_handle.delete(ctx).await?;
+        ctx.send(|m| m.content("That playlist is empty.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let space = settings.queue_space(handler_lock.lock().await.queue().len());
+    if space == 0 {
+        reply_handle.delete(ctx).await?;
+        ctx.send(|m| m.content("The queue is full.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+    playlist.entries.truncate(space);
+
+    // Every song is resolved before any is queued, so a failure can't leave half a playlist.
+    let mut songs = Vec::with_capacity(playlist.entries.len());
+    for entry in &playlist.entries {
+        let filters = GuildFilters::new(ctx.data(), guild_id);
+        songs.push(lazy_song_input(entry.clone(), filters).await?);
+    }
+
+    {
+        let mut handler = handler_lock.lock().await;
+        for song in songs {
+            let (track, _) = ctx
+                .data()
+                .guilds
+                .with(guild_id, |g| g.create_track(song, Some(ctx.author().id)));
+            handler.enqueue(track);
+        }
+    }
+    snapshot_queue(ctx, handler_lock).await;
+
+    let title = playlist.title.as_deref().unwrap_or("a playlist");
+    let volume = ctx.data().guilds.with(guild_id, |g| {
+        if let Some(url) = &playlist.url {
+            g.remember_song(title, url);
+        }
+        g.volume
+    });
+
+    debug!(
+        "Enqueued {} songs from `{title}` in {}.",
+        playlist.entries.len(),
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    reply_handle
+        .edit(ctx, |m| {
+            m.content(format!(
+                "Queued {} songs from *{title}*.",
+                playlist.entries.len()
+            ))
+            .embed(|e| playlist_embed(e, settings.embed_colour(), &playlist, volume))
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Get the call for this guild, joining the given or the author's voice channel if necessary.
+///
+/// Returns `None` if no channel could be joined, after telling the user why. The returned flag is
+/// `true` if the call was just joined.
+pub(crate) async fn join_voice_channel(
+    ctx: Context<'_>,
+    voice_channel: Option<GuildChannel>,
+) -> Result<Option<(Arc<Mutex<Call>>, bool)>> {
     let Some(manager) = songbird::get(ctx.serenity_context()).await else {
         return Err(anyhow!(SONGBIRD_MANAGER_ERR));
     };
     let guild_id = ctx.guild_id().unwrap();
-    let guild_name = guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string());
+    let settings = ctx.data().settings.get(guild_id);
     let mut first_play = false;
 
     let handler_lock = if let Some(handler_lock) = manager.get(guild_id) {
@@ -41,13 +445,13 @@ pub(crate) async fn play(
                         .ephemeral(true)
                 })
                 .await?;
-                return Ok(());
+                return Ok(None);
             }
         } else {
             let guild = ctx.guild().unwrap();
             let Some(channel_id) = guild.voice_states.get(&ctx.author().id).and_then(|voice_state| voice_state.channel_id) else {
                     ctx.send(|m| m.content("I'm not in a voice channel. Join or specify one.").ephemeral(true)).await?;
-                    return Ok(());
+                    return Ok(None);
                 };
             channel_id
         };
@@ -55,55 +459,29 @@ pub(crate) async fn play(
         res?;
 
         first_play = true;
+        let announce_channel = settings
+            .announce_channel
+            .unwrap_or_else(|| ctx.channel_id());
+        ctx.data().guilds.with(guild_id, |g| {
+            g.announce_channel = Some(announce_channel);
+            g.summoned_channel = Some(ctx.channel_id());
+            // A volume chosen before joining, or before leaving last time, still holds.
+            if !g.volume_chosen {
+                g.volume = settings.default_volume();
+            }
+        });
 
-        {
-            let mut handler = handler_lock.lock().await;
-            handler.add_global_event(
-                Event::Track(TrackEvent::Play),
-                NowPlaying::new(
-                    ctx.serenity_context().cache.clone(),
-                    ctx.channel_id(),
-                    guild_name.clone(),
-                    ctx.serenity_context().http.clone(),
-                ),
-            );
-        }
+        add_call_events(
+            &mut *handler_lock.lock().await,
+            &handler_lock,
+            manager,
+            ctx.serenity_context(),
+            ctx.data(),
+            guild_id,
+        );
 
         handler_lock
     };
 
-    ctx.defer().await?;
-
-    trace!(
-        "{} ran a YouTube search for `{}`.",
-        format_user_for_log(ctx.author()),
-        song
-    );
-
-    let song: Input = if let Ok(song) = Restartable::ytdl(song.clone(), true).await {
-        song
-    } else {
-        Restartable::ytdl_search(song.clone(), true).await?
-    }
-    .into();
-    let title = song.metadata.title.as_ref().unwrap();
-
-    debug!("Enqueued `{title}` in {guild_name}.");
-
-    ctx.send(|m| {
-        m.content(if first_play {
-            format!("Now playing *{title}*.")
-        } else {
-            format!("Queued *{title}*.")
-        })
-        .embed(|e| song_embed(e, &song.metadata))
-    })
-    .await?;
-
-    {
-        let mut handler = handler_lock.lock().await;
-        handler.enqueue_source(song);
-    }
-
-    Ok(())
+    Ok(Some((handler_lock, first_play)))
 }
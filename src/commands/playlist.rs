@@ -0,0 +1,435 @@
+use std::time::Duration;
+
+use log::{debug, error, warn};
+use poise::{
+    command,
+    serenity_prelude::{
+        CollectComponentInteraction, GuildChannel, GuildId, InteractionResponseType,
+    },
+};
+
+use crate::{
+    commands::play::{enqueue_entries, join_voice_channel},
+    filters::GuildFilters,
+    format::{
+        saved_playlist_message, saved_playlist_message_edit, saved_playlists_embed, turn_page,
+    },
+    fuzzy::fuzzy_score,
+    local::{is_file_url, song_input},
+    permissions::is_dj,
+    playlists::{PlaylistOwner, PlaylistSong, SavedPlaylist},
+    types::*,
+    ytdl::{is_url, ytdl_metadata},
+};
+
+/// Suggest saved playlists matching what has been typed so far.
+async fn autocomplete_playlist(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![];
+    };
+
+    ctx.data()
+        .playlists
+        .visible(ctx.author().id, guild_id)
+        .into_iter()
+        .filter(|playlist| fuzzy_score(partial, &playlist.name).is_some())
+        .map(|playlist| playlist.name)
+        .take(25)
+        .collect()
+}
+
+/// Find the playlist called `name`, telling the user if there isn't one.
+async fn find_playlist(ctx: Context<'_>, name: &str) -> Result<Option<SavedPlaylist>> {
+    let guild_id = ctx.guild_id().unwrap();
+    let playlist = ctx.data().playlists.find(ctx.author().id, guild_id, name);
+    if playlist.is_none() {
+        ctx.send(|m| m.content("That playlist doesn't exist.").ephemeral(true))
+            .await?;
+    }
+    Ok(playlist)
+}
+
+/// Find the playlist called `name` if the user may change it, telling them if they can't.
+///
+/// Anyone can change their own playlists, but only DJs can change shared ones.
+async fn find_editable_playlist(ctx: Context<'_>, name: &str) -> Result<Option<SavedPlaylist>> {
+    let Some(playlist) = find_playlist(ctx, name).await? else {
+        return Ok(None);
+    };
+
+    if let PlaylistOwner::Guild(guild_id) = playlist.owner {
+        if !author_is_dj(ctx, guild_id).await {
+            ctx.send(|m| {
+                m.content("Only DJs can change shared playlists.")
+                    .ephemeral(true)
+            })
+            .await?;
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(playlist))
+}
+
+/// Playlist names must fit in an autocomplete choice.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().count() <= 100
+}
+
+async fn author_is_dj(ctx: Context<'_>, guild_id: GuildId) -> bool {
+    let dj_role = ctx.data().settings.get(guild_id).dj_role;
+    match ctx.author_member().await {
+        Some(member) => is_dj(
+            ctx.serenity_context().cache.as_ref(),
+            guild_id,
+            &member,
+            dj_role,
+        ),
+        None => false,
+    }
+}
+
+/// Manage saved playlists.
+#[command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "playlist_create",
+        "playlist_add",
+        "playlist_remove",
+        "playlist_list",
+        "playlist_show",
+        "playlist_play",
+        "playlist_rename",
+        "playlist_delete"
+    )
+)]
+pub(crate) async fn playlist(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// Create a playlist.
+#[command(slash_command, guild_only, rename = "create")]
+pub(crate) async fn playlist_create(
+    ctx: Context<'_>,
+    #[description = "Name of the playlist."] name: String,
+    #[description = "Share the playlist with the whole server (DJs only)."] shared: Option<bool>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let name = name.trim().to_string();
+    if !valid_name(&name) {
+        ctx.send(|m| m.content("Invalid playlist name.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let owner = if shared.unwrap_or(false) {
+        if !author_is_dj(ctx, guild_id).await {
+            ctx.send(|m| {
+                m.content("Only DJs can create shared playlists.")
+                    .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
+        }
+        PlaylistOwner::Guild(guild_id)
+    } else {
+        PlaylistOwner::User(ctx.author().id)
+    };
+
+    let created = ctx.data().playlists.create(SavedPlaylist {
+        name: name.clone(),
+        owner,
+        songs: vec![],
+    })?;
+    if !created {
+        ctx.send(|m| {
+            m.content("A playlist with that name already exists.")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    ctx.say(format!("Created the playlist *{name}*.")).await?;
+    debug!(
+        "Created the playlist `{name}` in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    Ok(())
+}
+
+/// Add a song to a playlist.
+#[command(slash_command, guild_only, rename = "add")]
+pub(crate) async fn playlist_add(
+    ctx: Context<'_>,
+    #[description = "Name of the playlist."]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "The song to add (YouTube search or URL). Adds the current song if not given."]
+    song: Option<String>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(playlist) = find_editable_playlist(ctx, &name).await? else {
+        return Ok(());
+    };
+
+    let metadata = if let Some(song) = song {
+        ctx.defer().await?;
+        if is_file_url(&song) {
+            let filters = GuildFilters::new(ctx.data(), guild_id);
+            *song_input(song, filters).await?.metadata
+        } else if is_url(&song) {
+            ytdl_metadata(&song).await?
+        } else {
+            ytdl_metadata(&format!("ytsearch1:{song}")).await?
+        }
+    } else {
+        let np = match songbird::get(ctx.serenity_context())
+            .await
+            .and_then(|manager| manager.get(guild_id))
+        {
+            Some(handler_lock) => handler_lock.lock().await.queue().current(),
+            None => None,
+        };
+        let Some(np) = np else {
+            ctx.send(|m| m.content("I'm not playing a song.").ephemeral(true)).await?;
+            return Ok(());
+        };
+        np.metadata().clone()
+    };
+
+    let Some(song) = PlaylistSong::from_metadata(&metadata) else {
+        ctx.send(|m| m.content("That song can't be saved.").ephemeral(true))
+            .await?;
+        return Ok(());
+    };
+
+    let title = song.title.clone();
+    ctx.data()
+        .playlists
+        .update(playlist.owner, &playlist.name, |p| p.songs.push(song))?;
+
+    ctx.say(format!("Added *{title}* to *{}*.", playlist.name))
+        .await?;
+
+    Ok(())
+}
+
+/// Remove a song from a playlist.
+#[command(slash_command, guild_only, rename = "remove")]
+pub(crate) async fn playlist_remove(
+    ctx: Context<'_>,
+    #[description = "Name of the playlist."]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "Song number to remove."]
+    #[min = 1]
+    n: usize,
+) -> Result<()> {
+    let Some(playlist) = find_editable_playlist(ctx, &name).await? else {
+        return Ok(());
+    };
+
+    let removed = ctx
+        .data()
+        .playlists
+        .update(playlist.owner, &playlist.name, |p| {
+            (1..=p.songs.len())
+                .contains(&n)
+                .then(|| p.songs.remove(n - 1))
+        })?
+        .flatten();
+    let Some(song) = removed else {
+        ctx.send(|m| m.content("Invalid song number.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    ctx.say(format!("Removed *{}* from *{}*.", song.title, playlist.name))
+        .await?;
+
+    Ok(())
+}
+
+/// List your playlists and this server's shared playlists.
+#[command(slash_command, guild_only, rename = "list")]
+pub(crate) async fn playlist_list(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let playlists = ctx.data().playlists.visible(ctx.author().id, guild_id);
+
+    if playlists.is_empty() {
+        ctx.send(|m| {
+            m.content("There are no playlists. Create one with `/playlist create`.")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let colour = ctx.data().settings.get(guild_id).embed_colour();
+    ctx.send(|m| m.embed(|e| saved_playlists_embed(e, colour, &playlists)))
+        .await?;
+
+    Ok(())
+}
+
+/// View the songs in a playlist.
+#[command(slash_command, guild_only, rename = "show")]
+pub(crate) async fn playlist_show(
+    ctx: Context<'_>,
+    #[description = "Name of the playlist."]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "Playlist page"] page: Option<usize>,
+) -> Result<()> {
+    let mut page = page.unwrap_or(0);
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = ctx.data().settings.get(guild_id);
+    let Some(playlist) = find_playlist(ctx, &name).await? else {
+        return Ok(());
+    };
+
+    let reply_handle = ctx
+        .send(|m| {
+            let (m, new_page) = saved_playlist_message(m, &settings, &playlist, page, false);
+            page = new_page;
+            m
+        })
+        .await?;
+
+    while let Some(interaction) = CollectComponentInteraction::new(ctx)
+        .author_id(ctx.author().id)
+        .message_id(reply_handle.message().await?.id)
+        .timeout(Duration::from_secs(60))
+        .await
+    {
+        let Some(new_page) = turn_page(&interaction.data.custom_id, page) else {
+            warn!("Unknown interaction `{}`,", interaction.data.custom_id);
+            continue;
+        };
+        page = new_page;
+
+        let mut msg = interaction.message.clone();
+        msg.edit(ctx, |m| {
+            let (m, new_page) = saved_playlist_message_edit(m, &settings, &playlist, page);
+            page = new_page;
+            m
+        })
+        .await?;
+
+        if let Err(e) = interaction
+            .create_interaction_response(ctx, |r| {
+                r.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await
+        {
+            error!("Error while creating interaction response for playlist: {e}");
+        };
+    }
+
+    reply_handle
+        .edit(ctx, |m| {
+            saved_playlist_message(m, &settings, &playlist, page, true).0
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Add every song in a playlist to the queue.
+#[command(slash_command, guild_only, rename = "play")]
+pub(crate) async fn playlist_play(
+    ctx: Context<'_>,
+    #[description = "Name of the playlist."]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "Shuffle the songs."] shuffle: Option<bool>,
+    #[description = "The voice channel to join."] voice_channel: Option<GuildChannel>,
+) -> Result<()> {
+    let Some(playlist) = find_playlist(ctx, &name).await? else {
+        return Ok(());
+    };
+
+    let Some((handler_lock, _)) = join_voice_channel(ctx, voice_channel).await? else {
+        return Ok(());
+    };
+
+    let reply_handle = ctx.say(format!("Queueing *{}*…", playlist.name)).await?;
+
+    let mut turn = ctx
+        .data()
+        .guilds
+        .with(ctx.guild_id().unwrap(), |g| g.enqueue_turn());
+    turn.wait().await;
+    enqueue_entries(
+        ctx,
+        &handler_lock,
+        reply_handle,
+        playlist.to_playlist(),
+        None,
+        shuffle.unwrap_or(false),
+    )
+    .await
+}
+
+/// Rename a playlist.
+#[command(slash_command, guild_only, rename = "rename")]
+pub(crate) async fn playlist_rename(
+    ctx: Context<'_>,
+    #[description = "Name of the playlist."]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+    #[description = "New name of the playlist."] new_name: String,
+) -> Result<()> {
+    let new_name = new_name.trim().to_string();
+    if !valid_name(&new_name) {
+        ctx.send(|m| m.content("Invalid playlist name.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let Some(playlist) = find_editable_playlist(ctx, &name).await? else {
+        return Ok(());
+    };
+
+    if !ctx
+        .data()
+        .playlists
+        .rename(playlist.owner, &playlist.name, &new_name)?
+    {
+        ctx.send(|m| {
+            m.content("A playlist with that name already exists.")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    ctx.say(format!("Renamed *{}* to *{new_name}*.", playlist.name))
+        .await?;
+
+    Ok(())
+}
+
+/// Delete a playlist.
+#[command(slash_command, guild_only, rename = "delete")]
+pub(crate) async fn playlist_delete(
+    ctx: Context<'_>,
+    #[description = "Name of the playlist."]
+    #[autocomplete = "autocomplete_playlist"]
+    name: String,
+) -> Result<()> {
+    let Some(playlist) = find_editable_playlist(ctx, &name).await? else {
+        return Ok(());
+    };
+
+    ctx.data()
+        .playlists
+        .delete(playlist.owner, &playlist.name)?;
+
+    ctx.say(format!("Deleted the playlist *{}*.", playlist.name))
+        .await?;
+
+    Ok(())
+}
@@ -8,7 +8,7 @@ use poise::{
 };
 
 use crate::{
-    format::{queue_message, queue_message_edit},
+    format::{queue_message, queue_message_edit, turn_page},
     types::*,
 };
 
@@ -20,6 +20,11 @@ pub(crate) async fn queue(
 ) -> Result<()> {
     let mut page = page.unwrap_or(0);
     let guild_id = ctx.guild_id().unwrap();
+    let (loop_mode, filters) = ctx
+        .data()
+        .guilds
+        .with(guild_id, |g| (g.loop_mode, g.filters.clone()));
+    let settings = ctx.data().settings.get(guild_id);
     let Some(manager) = songbird::get(ctx.serenity_context()).await else {
         return Err(anyhow!(SONGBIRD_MANAGER_ERR));
     };
@@ -40,7 +45,8 @@ pub(crate) async fn queue(
         return Ok(());
     } else {
         ctx.send(|m| {
-            let (m, new_page) = queue_message(m, &queue, page, false);
+            let (m, new_page) =
+                queue_message(m, &settings, &queue, page, loop_mode, &filters, false);
             page = new_page;
             m
         })
@@ -53,20 +59,15 @@ pub(crate) async fn queue(
         .timeout(Duration::from_secs(60))
         .await
     {
-        match &*interaction.data.custom_id {
-            "first" => page = 0,
-            "previous" => page = page.saturating_sub(1),
-            "next" => page = page.saturating_add(1),
-            "last" => page = usize::MAX,
-            other => {
-                warn!("Unknown interaction `{other}`,");
-                continue;
-            }
-        }
+        let Some(new_page) = turn_page(&interaction.data.custom_id, page) else {
+            warn!("Unknown interaction `{}`,", interaction.data.custom_id);
+            continue;
+        };
+        page = new_page;
 
         let mut msg = interaction.message.clone();
         msg.edit(ctx, |m| {
-            let (m, new_page) = queue_message_edit(m, &queue, page);
+            let (m, new_page) = queue_message_edit(m, &settings, &queue, page, loop_mode, &filters);
             page = new_page;
             m
         })
@@ -83,7 +84,9 @@ pub(crate) async fn queue(
     }
 
     reply_handle
-        .edit(ctx, |m| queue_message(m, &queue, page, true).0)
+        .edit(ctx, |m| {
+            queue_message(m, &settings, &queue, page, loop_mode, &filters, true).0
+        })
         .await?;
 
     Ok(())
@@ -2,7 +2,13 @@ use anyhow::anyhow;
 use log::error;
 use poise::command;
 
-use crate::{format::song_embed, types::*};
+use crate::{
+    format::song_embed,
+    permissions::is_dj,
+    snapshot::snapshot_queue,
+    track::{requester, TrackInfo},
+    types::*,
+};
 
 /// Remove a song from the queue.
 #[command(slash_command, guild_only)]
@@ -11,6 +17,8 @@ pub(crate) async fn remove(
     #[description = "Song number (based on queue) to remove"] n: usize,
 ) -> Result<()> {
     let guild_id = ctx.guild_id().unwrap();
+    let volume = ctx.data().guilds.with(guild_id, |g| g.volume);
+    let settings = ctx.data().settings.get(guild_id);
     let Some(manager) = songbird::get(ctx.serenity_context()).await else {
         return Err(anyhow!(SONGBIRD_MANAGER_ERR));
     };
@@ -20,6 +28,16 @@ pub(crate) async fn remove(
         return Ok(());
     };
 
+    let is_dj = match ctx.author_member().await {
+        Some(member) => is_dj(
+            ctx.serenity_context().cache.as_ref(),
+            guild_id,
+            &member,
+            settings.dj_role,
+        ),
+        None => false,
+    };
+
     let song = {
         let handler = handler_lock.lock().await;
         let queue = handler.queue();
@@ -33,6 +51,14 @@ pub(crate) async fn remove(
                 .await?;
             return Ok(());
         }
+        if !is_dj && queue.current_queue().get(n).and_then(requester) != Some(ctx.author().id) {
+            ctx.send(|m| {
+                m.content("You can only remove songs you requested.")
+                    .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
+        }
 
         let mut song = None;
         queue.modify_queue(|q| {
@@ -44,11 +70,12 @@ pub(crate) async fn remove(
         });
         song.unwrap()
     };
+    snapshot_queue(ctx, &handler_lock).await;
 
     ctx.send(|m| {
-        let metadata = song.metadata();
-        m.content(format!("Removed *{}*.", metadata.title.as_ref().unwrap()))
-            .embed(|e| song_embed(e, metadata))
+        let info = TrackInfo::of(&song);
+        m.content(format!("Removed *{}*.", info.title))
+            .embed(|e| song_embed(e, settings.embed_colour(), &info, requester(&song), volume))
     })
     .await?;
 
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use log::{debug, error, trace};
+use poise::{
+    command,
+    serenity_prelude::{CollectComponentInteraction, GuildChannel, InteractionResponseType},
+};
+use songbird::input::{Input, Restartable};
+
+use crate::{
+    commands::play::join_voice_channel,
+    filters::GuildFilters,
+    format::{create_search_components, format_user_for_log, search_embed, song_embed},
+    snapshot::snapshot_queue,
+    track::TrackInfo,
+    types::*,
+    ytdl::{search_songs, LazyYtdl},
+};
+
+/// Search for a song and choose which result to play.
+#[command(slash_command, guild_only)]
+pub(crate) async fn search(
+    ctx: Context<'_>,
+    #[description = "YouTube search query."] query: String,
+    #[description = "Number of results to show (1-10)."]
+    #[min = 1]
+    #[max = 10]
+    results: Option<usize>,
+    #[description = "The voice channel to join."] voice_channel: Option<GuildChannel>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = ctx.data().settings.get(guild_id);
+
+    // Not deferred, so finding nothing can still be shown only to the author in place of this.
+    let reply_handle = ctx.say(format!("Searching for *{query}*…")).await?;
+
+    trace!(
+        "{} ran a YouTube search for `{}`.",
+        format_user_for_log(ctx.author()),
+        query
+    );
+
+    let mut results = search_songs(&query, results.unwrap_or(5).clamp(1, 10)).await?;
+    results.retain(|song| settings.allows_duration(song.duration.as_ref()));
+    if results.is_empty() {
+        reply_handle.delete(ctx).await?;
+        ctx.send(|m| m.content("No results found.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+    let infos: Vec<TrackInfo> = results.iter().map(TrackInfo::from).collect();
+
+    reply_handle
+        .edit(ctx, |m| {
+            m.content("Choose a song to play.")
+                .embed(|e| search_embed(e, settings.embed_colour(), &query, &infos))
+                .components(|c| create_search_components(c, &infos, false))
+        })
+        .await?;
+
+    let interaction = CollectComponentInteraction::new(ctx)
+        .author_id(ctx.author().id)
+        .message_id(reply_handle.message().await?.id)
+        .timeout(Duration::from_secs(60))
+        .await;
+
+    let Some(song) = interaction.as_ref().and_then(|interaction| {
+        let i = interaction.data.values.first()?.parse::<usize>().ok()?;
+        results.get(i)
+    }) else {
+        reply_handle
+            .edit(ctx, |m| {
+                m.content("No song was chosen.")
+                    .components(|c| create_search_components(c, &infos, true))
+            })
+            .await?;
+        return Ok(());
+    };
+
+    if let Some(interaction) = &interaction {
+        if let Err(e) = interaction
+            .create_interaction_response(ctx, |r| {
+                r.kind(InteractionResponseType::DeferredUpdateMessage)
+            })
+            .await
+        {
+            error!("Error while creating interaction response for search: {e}");
+        }
+    }
+
+    // Joined only once a song is chosen, so a search that's abandoned doesn't leave the bot idling
+    // in a voice channel.
+    let Some((handler_lock, _)) = join_voice_channel(ctx, voice_channel).await? else {
+        reply_handle
+            .edit(ctx, |m| {
+                m.components(|c| create_search_components(c, &infos, true))
+            })
+            .await?;
+        return Ok(());
+    };
+
+    // Taken once the song is chosen, as that's when it was asked for.
+    let mut turn = ctx.data().guilds.with(guild_id, |g| g.enqueue_turn());
+
+    let info = TrackInfo::from(song);
+    let title = &info.title;
+    let volume = ctx.data().guilds.with(guild_id, |g| {
+        if let Some(url) = &info.url {
+            g.remember_song(title, url);
+        }
+        g.volume
+    });
+
+    let input: Input = Restartable::new(
+        LazyYtdl::new(song.clone(), GuildFilters::new(ctx.data(), guild_id)),
+        true,
+    )
+    .await?
+    .into();
+    turn.wait().await;
+    {
+        let mut handler = handler_lock.lock().await;
+        if settings.queue_space(handler.queue().len()) == 0 {
+            reply_handle
+                .edit(ctx, |m| {
+                    m.content("The queue is full.")
+                        .components(|c| create_search_components(c, &infos, true))
+                })
+                .await?;
+            return Ok(());
+        }
+
+        let (track, _) = ctx
+            .data()
+            .guilds
+            .with(guild_id, |g| g.create_track(input, Some(ctx.author().id)));
+        handler.enqueue(track);
+    }
+    snapshot_queue(ctx, &handler_lock).await;
+    drop(turn);
+
+    debug!(
+        "Enqueued `{title}` in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    reply_handle
+        .edit(ctx, |m| {
+            m.content(format!("Queued *{title}*."))
+                .embed(|e| {
+                    song_embed(
+                        e,
+                        settings.embed_colour(),
+                        &info,
+                        Some(ctx.author().id),
+                        volume,
+                    )
+                })
+                .components(|c| create_search_components(c, &infos, true))
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use log::debug;
+use poise::command;
+
+use crate::{
+    format::{format_duration, parse_timestamp, song_embed_with_footer},
+    track::{requester, seek_anchor, TrackInfo},
+    types::*,
+};
+
+/// Furthest into a song that can be seeked to, so livestreams and songs of unknown length can't be
+/// seeked anywhere too far to count.
+const MAX_POSITION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Seek to a position in the current song.
+#[command(slash_command, guild_only)]
+pub(crate) async fn seek(
+    ctx: Context<'_>,
+    #[description = "Position to seek to (e.g. 1:23, 90s, +30, -15)"] position: String,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let Some(handler_lock) = manager.get(guild_id) else {
+        ctx.send(|m| m.content("I'm not in a voice channel.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let Some(timestamp) = parse_timestamp(&position) else {
+        ctx.send(|m| m.content("Invalid position.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let np = {
+        let handler = handler_lock.lock().await;
+        handler.queue().current()
+    };
+
+    let Some(np) = np else {
+        ctx.send(|m| m.content("I'm not playing a song.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    if !np.is_seekable() {
+        ctx.send(|m| m.content("This song can't be seeked.").ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+
+    let info = np.get_info().await?;
+    let speed = ctx
+        .data()
+        .guilds
+        .with(guild_id, |g| g.filters.speed_factor());
+    let anchor = seek_anchor(&np, speed);
+    let current = anchor.song_position(info.position);
+    let position = timestamp.resolve(current);
+    if position > MAX_POSITION
+        || np
+            .metadata()
+            .duration
+            .is_some_and(|duration| position > duration)
+    {
+        ctx.send(|m| {
+            m.content("That's past the end of the song.")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    // Songbird seeks forward by skipping audio, but has to restart the source to go back.
+    let restarted = position < current
+        && ctx
+            .data()
+            .guilds
+            .restart_track(guild_id, &np, position)
+            .await?;
+    if !restarted {
+        let output = anchor
+            .output_position(position)
+            .ok_or_else(|| anyhow!("Seek position {position:?} is out of range."))?;
+        np.seek_time(output)?;
+    }
+
+    let position = format_duration(&position);
+    debug!(
+        "Seeked to {position} in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    ctx.send(|m| {
+        m.content(format!("Seeked to `{position}`.")).embed(|e| {
+            song_embed_with_footer(
+                e,
+                ctx.data().settings.get(guild_id).embed_colour(),
+                &TrackInfo::of(&np),
+                requester(&np),
+                info.volume,
+                vec![format!("At {position}")],
+            )
+        })
+    })
+    .await?;
+
+    Ok(())
+}
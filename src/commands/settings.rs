@@ -0,0 +1,321 @@
+use log::debug;
+use poise::{
+    command,
+    serenity_prelude::{ChannelType, GuildChannel, Mentionable, Role},
+};
+
+use crate::{
+    format::{base_embed, format_duration, format_volume, parse_colour, parse_duration},
+    settings::{Setting, MAX_FADE, MAX_PAGE_SIZE},
+    types::*,
+};
+
+/// View or change this server's settings.
+#[command(
+    slash_command,
+    guild_only,
+    subcommands("settings_view", "settings_set", "settings_reset"),
+    default_member_permissions = "MANAGE_GUILD",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn settings(_ctx: Context<'_>) -> Result<()> {
+    Ok(())
+}
+
+/// View this server's settings.
+#[command(
+    slash_command,
+    guild_only,
+    rename = "view",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn settings_view(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = ctx.data().settings.get(guild_id);
+
+    let or_default = |value: Option<String>, default: &str| {
+        value.unwrap_or_else(|| format!("{default} *(default)*"))
+    };
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            base_embed(e, settings.embed_colour())
+                .title("Settings")
+                .field(
+                    "Announce channel",
+                    or_default(
+                        settings
+                            .announce_channel
+                            .map(|channel| channel.mention().to_string()),
+                        "Where I was summoned",
+                    ),
+                    true,
+                )
+                .field(
+                    "DJ role",
+                    or_default(
+                        settings.dj_role.map(|role| role.mention().to_string()),
+                        "Set by the bot owner",
+                    ),
+                    true,
+                )
+                .field(
+                    "Default volume",
+                    format_volume(settings.default_volume()),
+                    true,
+                )
+                .field(
+                    "Max queue length",
+                    or_default(
+                        settings.max_queue_length.map(|len| len.to_string()),
+                        "Unlimited",
+                    ),
+                    true,
+                )
+                .field(
+                    "Max song length",
+                    or_default(
+                        settings
+                            .max_track_duration()
+                            .map(|duration| format!("`{}`", format_duration(&duration))),
+                        "Unlimited",
+                    ),
+                    true,
+                )
+                .field(
+                    "Embed colour",
+                    format!("`#{:06x}`", settings.embed_colour()),
+                    true,
+                )
+                .field("Page size", settings.page_size().to_string(), true)
+                .field(
+                    "Restore queue",
+                    if settings.restore_queue() {
+                        "On"
+                    } else {
+                        "Off"
+                    },
+                    true,
+                )
+                .field(
+                    "Normalize loudness",
+                    if settings.normalize_loudness() {
+                        "On"
+                    } else {
+                        "Off"
+                    },
+                    true,
+                )
+                .field(
+                    "Fade",
+                    settings.fade().map_or_else(
+                        || "Off".to_string(),
+                        |fade| format!("`{}`", format_duration(&fade)),
+                    ),
+                    true,
+                )
+        })
+        .ephemeral(true)
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Change this server's settings.
+#[command(
+    slash_command,
+    guild_only,
+    rename = "set",
+    required_permissions = "MANAGE_GUILD"
+)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn settings_set(
+    ctx: Context<'_>,
+    #[description = "Channel where new songs are announced."] announce_channel: Option<
+        GuildChannel,
+    >,
+    #[description = "Role that can skip and remove anyone's songs."] dj_role: Option<Role>,
+    #[description = "Volume percentage when I join a voice channel (0-200)."]
+    #[min = 0]
+    #[max = 200]
+    default_volume: Option<u16>,
+    #[description = "Maximum number of songs in the queue."]
+    #[min = 1]
+    max_queue_length: Option<usize>,
+    #[description = "Maximum length of a song, e.g. 10m or 1:30:00."] max_song_length: Option<
+        String,
+    >,
+    #[description = "Colour of my embeds, e.g. #0789f0."] embed_colour: Option<String>,
+    #[description = "Number of songs per queue page."]
+    #[min = 1]
+    #[max = 10]
+    page_size: Option<usize>,
+    #[description = "Rejoin and restore the queue after I restart."] restore_queue: Option<bool>,
+    #[description = "Even out the loudness of songs, from the next song on."]
+    normalize_loudness: Option<bool>,
+    #[description = "Seconds to fade each song out and the next one in over, or 0 for none (0-10)."]
+    #[min = 0]
+    #[max = 10]
+    fade: Option<u64>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    if let Some(channel) = &announce_channel {
+        if channel.kind != ChannelType::Text {
+            ctx.send(|m| {
+                m.content(format!("{} is not a text channel.", channel.mention()))
+                    .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
+        }
+    }
+
+    let max_track_duration = match max_song_length.as_deref().map(parse_duration) {
+        Some(Some(duration)) if !duration.is_zero() => Some(duration.as_secs()),
+        Some(_) => {
+            ctx.send(|m| m.content("Invalid song length.").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+        None => None,
+    };
+
+    let embed_colour = match embed_colour.as_deref().map(parse_colour) {
+        Some(Some(colour)) => Some(colour),
+        Some(None) => {
+            ctx.send(|m| m.content("Invalid colour.").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+        None => None,
+    };
+
+    let page_size = page_size.map(|size| size.clamp(1, MAX_PAGE_SIZE));
+
+    if announce_channel.is_none()
+        && dj_role.is_none()
+        && default_volume.is_none()
+        && max_queue_length.is_none()
+        && max_track_duration.is_none()
+        && embed_colour.is_none()
+        && page_size.is_none()
+        && restore_queue.is_none()
+        && normalize_loudness.is_none()
+        && fade.is_none()
+    {
+        ctx.send(|m| {
+            m.content("Specify at least one setting to change.")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    ctx.data().settings.update(guild_id, |settings| {
+        if let Some(channel) = &announce_channel {
+            settings.announce_channel = Some(channel.id);
+        }
+        if let Some(role) = &dj_role {
+            settings.dj_role = Some(role.id);
+        }
+        if let Some(volume) = default_volume {
+            settings.default_volume = Some(volume.min(200));
+        }
+        if let Some(len) = max_queue_length {
+            settings.max_queue_length = Some(len);
+        }
+        if let Some(secs) = max_track_duration {
+            settings.max_track_duration = Some(secs);
+        }
+        if let Some(colour) = embed_colour {
+            settings.embed_colour = Some(colour);
+        }
+        if let Some(size) = page_size {
+            settings.page_size = Some(size);
+        }
+        if let Some(restore) = restore_queue {
+            settings.restore_queue = Some(restore);
+        }
+        if let Some(normalize) = normalize_loudness {
+            settings.normalize_loudness = Some(normalize);
+        }
+        if let Some(secs) = fade {
+            settings.fade = Some(secs.min(MAX_FADE));
+        }
+    })
+    .await?;
+
+    if restore_queue == Some(false) {
+        ctx.data().snapshots.set(guild_id, None);
+    }
+
+    if let Some(channel) = &announce_channel {
+        ctx.data().guilds.with(guild_id, |g| {
+            if g.announce_channel.is_some() {
+                g.announce_channel = Some(channel.id);
+            }
+        });
+    }
+
+    ctx.send(|m| m.content("Updated the settings.").ephemeral(true))
+        .await?;
+    debug!(
+        "Updated settings in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    Ok(())
+}
+
+/// Reset this server's settings to their defaults.
+#[command(
+    slash_command,
+    guild_only,
+    rename = "reset",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub(crate) async fn settings_reset(
+    ctx: Context<'_>,
+    #[description = "The setting to reset (all if not given)."] setting: Option<Setting>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    ctx.data()
+        .settings
+        .update(guild_id, |settings| match setting {
+            Some(setting) => settings.reset(setting),
+            None => *settings = Default::default(),
+        })
+        .await?;
+
+    if matches!(setting, None | Some(Setting::AnnounceChannel)) {
+        // Back to wherever the bot was summoned from, for the call it's in now.
+        ctx.data().guilds.with(guild_id, |g| {
+            if g.announce_channel.is_some() {
+                g.announce_channel = g.summoned_channel.or(g.announce_channel);
+            }
+        });
+    }
+
+    if !ctx.data().settings.get(guild_id).restore_queue() {
+        ctx.data().snapshots.set(guild_id, None);
+    }
+
+    ctx.send(|m| {
+        m.content(match setting {
+            Some(setting) => format!("Reset `{setting}`."),
+            None => "Reset all settings.".to_string(),
+        })
+        .ephemeral(true)
+    })
+    .await?;
+    debug!(
+        "Reset {} in {}.",
+        setting.map_or_else(|| "all settings".to_string(), |setting| setting.to_string()),
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use anyhow::anyhow;
+use log::debug;
+use poise::command;
+use rand::{seq::SliceRandom, thread_rng};
+
+use crate::{snapshot::snapshot_queue, types::*};
+
+/// Shuffle the upcoming songs in the queue.
+#[command(slash_command, guild_only)]
+pub(crate) async fn shuffle(ctx: Context<'_>) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let Some(handler_lock) = manager.get(guild_id) else {
+        ctx.send(|m| m.content("I'm not in a voice channel.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    {
+        let handler = handler_lock.lock().await;
+        let queue = handler.queue();
+        if queue.len() < 2 {
+            ctx.send(|m| m.content("There are no songs to shuffle.").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+
+        queue.modify_queue(|q| q.make_contiguous()[1..].shuffle(&mut thread_rng()));
+    }
+    snapshot_queue(ctx, &handler_lock).await;
+
+    ctx.say("Shuffled the queue.").await?;
+    debug!(
+        "Shuffled the queue in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    Ok(())
+}
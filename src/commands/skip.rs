@@ -1,8 +1,23 @@
+use std::time::Duration;
+
 use anyhow::anyhow;
-use log::error;
-use poise::command;
+use log::{debug, error};
+use poise::{
+    command,
+    serenity_prelude::{
+        ButtonStyle, ChannelId, CollectComponentInteraction, CreateComponents,
+        InteractionResponseType,
+    },
+};
+use songbird::tracks::{TrackHandle, TrackQueue};
 
-use crate::{format::song_embed, types::*};
+use crate::{
+    format::song_embed,
+    idle::{count_listeners, user_channel},
+    permissions::{is_dj, required_skip_votes},
+    track::{requester, TrackInfo},
+    types::*,
+};
 
 /// Skip through songs in the queue.
 #[command(slash_command, guild_only)]
@@ -12,6 +27,8 @@ pub(crate) async fn skip(
 ) -> Result<()> {
     let n = n.unwrap_or(1).max(1);
     let guild_id = ctx.guild_id().unwrap();
+    let volume = ctx.data().guilds.with(guild_id, |g| g.volume);
+    let settings = ctx.data().settings.get(guild_id);
     let Some(manager) = songbird::get(ctx.serenity_context()).await else {
         return Err(anyhow!(SONGBIRD_MANAGER_ERR));
     };
@@ -21,39 +38,208 @@ pub(crate) async fn skip(
         return Ok(());
     };
 
-    let mut first_song = None;
-    {
+    let (queue, channel) = {
         let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-        if queue.is_empty() {
-            ctx.send(|m| m.content("I'm not playing any songs.").ephemeral(true))
+        (handler.queue().clone(), handler.current_channel())
+    };
+
+    let Some(np) = queue.current() else {
+        ctx.send(|m| m.content("I'm not playing any songs.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let is_dj = match ctx.author_member().await {
+        Some(member) => is_dj(
+            ctx.serenity_context().cache.as_ref(),
+            guild_id,
+            &member,
+            settings.dj_role,
+        ),
+        None => false,
+    };
+
+    // Requesters may skip their own song, but skipping several would skip other people's too.
+    if is_dj || (n == 1 && requester(&np) == Some(ctx.author().id)) {
+        let first_song = skip_songs(&queue, n);
+
+        if n == 1 {
+            // The song may have ended by itself in the meantime, leaving nothing else to skip.
+            let song = first_song.unwrap_or(np);
+            let info = TrackInfo::of(&song);
+            ctx.send(|m| {
+                m.content(format!("Skipped *{}*.", info.title)).embed(|e| {
+                    song_embed(e, settings.embed_colour(), &info, requester(&song), volume)
+                })
+            })
+            .await?;
+        } else {
+            ctx.send(|m| m.content(format!("Skipped {n} songs.")))
                 .await?;
-            return Ok(());
         }
 
-        queue.modify_queue(|q| {
-            for _ in 0..n {
-                if let Some(s) = q.pop_front() {
-                    if let Err(e) = s.stop() {
-                        error!("Error while stopping track: {e}");
-                    }
-                    first_song = Some(s);
-                }
-            }
-        });
+        return Ok(());
     }
 
-    if n == 1 {
+    if n > 1 {
         ctx.send(|m| {
-            let metadata = first_song.as_ref().unwrap().metadata();
-            m.content(format!("Skipped *{}*.", metadata.title.as_ref().unwrap()))
-                .embed(|e| song_embed(e, metadata))
+            m.content("Only DJs can skip more than one song.")
+                .ephemeral(true)
         })
         .await?;
-    } else {
-        ctx.send(|m| m.content(format!("Skipped {n} songs.")))
+        return Ok(());
+    }
+
+    let cache = ctx.serenity_context().cache.as_ref();
+    let Some(channel) = channel.map(|channel| ChannelId(channel.0)) else {
+        ctx.send(|m| m.content("I'm not in a voice channel.").ephemeral(true)).await?;
+        return Ok(());
+    };
+    if user_channel(cache, guild_id, ctx.author().id) != Some(channel) {
+        ctx.send(|m| {
+            m.content("You need to be in my voice channel to vote.")
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let info = TrackInfo::of(&np);
+    let title = &info.title;
+    let (_, mut votes) = ctx
+        .data()
+        .guilds
+        .with(guild_id, |g| g.vote_skip(np.uuid(), ctx.author().id));
+    let mut required = required_skip_votes(count_listeners(cache, guild_id, channel));
+
+    if votes >= required {
+        skip_voted(&queue, &np);
+        ctx.send(|m| {
+            m.content(format!("Skipped *{title}*."))
+                .embed(|e| song_embed(e, settings.embed_colour(), &info, requester(&np), volume))
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let reply_handle = ctx
+        .send(|m| {
+            m.content(vote_message(title, votes, required))
+                .components(|c| create_vote_components(c, false))
+        })
+        .await?;
+
+    while let Some(interaction) = CollectComponentInteraction::new(ctx)
+        .message_id(reply_handle.message().await?.id)
+        .filter(|interaction| interaction.data.custom_id == "vote_skip")
+        .timeout(Duration::from_secs(60))
+        .await
+    {
+        if queue.current().map(|track| track.uuid()) != Some(np.uuid()) {
+            break;
+        }
+
+        let voter = interaction.user.id;
+        let in_channel = user_channel(cache, guild_id, voter) == Some(channel);
+        let new_vote = in_channel
+            && ctx.data().guilds.with(guild_id, |g| {
+                let (new, total) = g.vote_skip(np.uuid(), voter);
+                votes = total;
+                new
+            });
+
+        if let Err(e) = interaction
+            .create_interaction_response(ctx, |r| {
+                if !in_channel {
+                    r.interaction_response_data(|d| {
+                        d.content("You need to be in my voice channel to vote.")
+                            .ephemeral(true)
+                    })
+                } else if !new_vote {
+                    r.interaction_response_data(|d| {
+                        d.content("You've already voted.").ephemeral(true)
+                    })
+                } else {
+                    r.kind(InteractionResponseType::DeferredUpdateMessage)
+                }
+            })
+            .await
+        {
+            error!("Error while creating interaction response for skip vote: {e}");
+        }
+
+        if !new_vote {
+            continue;
+        }
+
+        required = required_skip_votes(count_listeners(cache, guild_id, channel));
+        if votes >= required {
+            skip_voted(&queue, &np);
+            break;
+        }
+
+        reply_handle
+            .edit(ctx, |m| {
+                m.content(vote_message(title, votes, required))
+                    .components(|c| create_vote_components(c, false))
+            })
             .await?;
     }
 
+    let content = if votes >= required {
+        format!("Skipped *{title}*.")
+    } else {
+        format!("Vote to skip *{title}* ended with {votes}/{required} votes.")
+    };
+    reply_handle
+        .edit(ctx, |m| {
+            m.content(content)
+                .components(|c| create_vote_components(c, true))
+        })
+        .await?;
+
     Ok(())
 }
+
+/// Skip the current song and the `n - 1` songs after it, returning the current song.
+pub(crate) fn skip_songs(queue: &TrackQueue, n: usize) -> Option<TrackHandle> {
+    queue.modify_queue(|q| {
+        for _ in 1..n {
+            if q.len() < 2 {
+                break;
+            }
+            if let Err(e) = q.remove(1).unwrap().stop() {
+                error!("Error while stopping track: {e}");
+            }
+        }
+    });
+
+    let current = queue.current();
+    // Stopping the current track lets the queue move on to the next one.
+    if let Err(e) = queue.skip() {
+        error!("Error while stopping track: {e}");
+    }
+    current
+}
+
+/// Skip `track` if it's still playing.
+pub(crate) fn skip_voted(queue: &TrackQueue, track: &TrackHandle) {
+    if queue.current().map(|current| current.uuid()) == Some(track.uuid()) {
+        debug!("Vote skipped `{}`.", TrackInfo::of(track).title);
+        skip_songs(queue, 1);
+    }
+}
+
+pub(crate) fn vote_message(title: &str, votes: usize, required: usize) -> String {
+    format!("Voting to skip *{title}*: {votes}/{required} votes.")
+}
+
+fn create_vote_components(c: &mut CreateComponents, disabled: bool) -> &mut CreateComponents {
+    c.create_action_row(|r| {
+        r.create_button(|b| {
+            b.custom_id("vote_skip")
+                .label("Vote to skip")
+                .style(ButtonStyle::Primary)
+                .disabled(disabled)
+        })
+    })
+}
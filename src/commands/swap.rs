@@ -0,0 +1,54 @@
+use anyhow::anyhow;
+use poise::command;
+
+use crate::{snapshot::snapshot_queue, track::TrackInfo, types::*};
+
+/// Swap two songs in the queue.
+#[command(slash_command, guild_only)]
+pub(crate) async fn swap(
+    ctx: Context<'_>,
+    #[description = "Song number (based on queue) to swap"] a: usize,
+    #[description = "Song number (based on queue) to swap with"] b: usize,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let Some(handler_lock) = manager.get(guild_id) else {
+        ctx.send(|m| m.content("I'm not in a voice channel.").ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let (first, second) = {
+        let handler = handler_lock.lock().await;
+        let queue = handler.queue();
+        if queue.is_empty() {
+            ctx.send(|m| m.content("I'm not playing any songs.").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+        // The currently playing song can't be swapped out.
+        if a == 0 || b == 0 || a >= queue.len() || b >= queue.len() {
+            ctx.send(|m| m.content("Invalid song number.").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+
+        queue.modify_queue(|q| {
+            let songs = (q[a].handle(), q[b].handle());
+            q.swap(a, b);
+            songs
+        })
+    };
+    snapshot_queue(ctx, &handler_lock).await;
+
+    ctx.say(format!(
+        "Swapped *{}* and *{}*.",
+        TrackInfo::of(&first).title,
+        TrackInfo::of(&second).title,
+    ))
+    .await?;
+
+    Ok(())
+}
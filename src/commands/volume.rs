@@ -0,0 +1,51 @@
+use anyhow::anyhow;
+use log::{debug, error};
+use poise::command;
+
+use crate::{format::format_volume, types::*};
+
+/// View or change the playback volume.
+#[command(slash_command, guild_only)]
+pub(crate) async fn volume(
+    ctx: Context<'_>,
+    #[description = "Volume percentage (0-200)"]
+    #[min = 0]
+    #[max = 200]
+    level: Option<u16>,
+) -> Result<()> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let Some(level) = level else {
+        let volume = format_volume(ctx.data().guilds.with(guild_id, |g| g.volume));
+        ctx.send(|m| m.content(format!("The volume is {volume}.")).ephemeral(true)).await?;
+        return Ok(());
+    };
+
+    let Some(manager) = songbird::get(ctx.serenity_context()).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let volume = f32::from(level.min(200)) / 100.0;
+    ctx.data().guilds.with(guild_id, |g| {
+        g.volume = volume;
+        g.volume_chosen = true;
+    });
+
+    if let Some(handler_lock) = manager.get(guild_id) {
+        let handler = handler_lock.lock().await;
+        for track in handler.queue().current_queue() {
+            if let Err(e) = track.set_volume(volume) {
+                error!("Error while setting track volume: {e}");
+            }
+        }
+    }
+
+    ctx.say(format!("Set the volume to {}.", format_volume(volume)))
+        .await?;
+    debug!(
+        "Set volume to {level}% in {}.",
+        guild_id.name(ctx).unwrap_or_else(|| guild_id.to_string()),
+    );
+
+    Ok(())
+}
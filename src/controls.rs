@@ -0,0 +1,287 @@
+use std::sync::atomic::Ordering;
+
+use anyhow::anyhow;
+use log::{debug, error};
+use poise::serenity_prelude::{
+    ChannelId, Context as SerenityContext, GuildId, Http, Message, MessageComponentInteraction,
+    UserId,
+};
+use rand::{seq::SliceRandom, thread_rng};
+use songbird::tracks::{PlayMode, TrackHandle, TrackQueue};
+
+use crate::{
+    commands::{
+        loop_mode::{loop_message, loop_tracks},
+        skip::{skip_songs, skip_voted, vote_message},
+    },
+    format::create_now_playing_components,
+    idle::{count_listeners, user_channel},
+    permissions::{is_dj, required_skip_votes},
+    state::{GuildStore, LoopMode},
+    track::{requester, TrackInfo},
+    types::{Data, Result, SONGBIRD_MANAGER_ERR},
+};
+
+/// A button on the now-playing panel.
+#[derive(Clone, Copy, Debug)]
+enum Control {
+    Pause,
+    Skip,
+    Stop,
+    Loop,
+    Shuffle,
+}
+
+impl Control {
+    fn from_custom_id(custom_id: &str) -> Option<Self> {
+        match custom_id {
+            "np_pause" => Some(Self::Pause),
+            "np_skip" => Some(Self::Skip),
+            "np_stop" => Some(Self::Stop),
+            "np_loop" => Some(Self::Loop),
+            "np_shuffle" => Some(Self::Shuffle),
+            _ => None,
+        }
+    }
+}
+
+/// What to tell the user who pressed a button.
+enum Reply {
+    /// Shown to everyone, like a slash command's reply.
+    Public(String),
+    /// Shown only to the user, for refusals.
+    Private(String),
+}
+
+/// Handle a press of one of the now-playing panel's buttons.
+///
+/// Other component interactions are left to the collectors waiting for them.
+pub(crate) async fn on_component_interaction(
+    ctx: &SerenityContext,
+    data: &Data,
+    interaction: &MessageComponentInteraction,
+) -> Result<()> {
+    let Some(control) = Control::from_custom_id(&interaction.data.custom_id) else {
+        return Ok(());
+    };
+    let Some(guild_id) = interaction.guild_id else {
+        return Ok(());
+    };
+    if data.shutting_down.load(Ordering::SeqCst) {
+        return respond(
+            ctx,
+            interaction,
+            Reply::Private("I'm restarting, try again in a moment.".into()),
+        )
+        .await;
+    }
+    let Some(manager) = songbird::get(ctx).await else {
+        return Err(anyhow!(SONGBIRD_MANAGER_ERR));
+    };
+
+    let panel = data.guilds.with(guild_id, |g| g.now_playing_panel);
+    let handler_lock = manager.get(guild_id);
+    let (Some(handler_lock), Some((_, message_id))) = (handler_lock, panel) else {
+        return respond(
+            ctx,
+            interaction,
+            Reply::Private("I'm not playing a song.".into()),
+        )
+        .await;
+    };
+    if message_id != interaction.message.id {
+        return respond(
+            ctx,
+            interaction,
+            Reply::Private("This song has already finished.".into()),
+        )
+        .await;
+    }
+
+    let (queue, channel) = {
+        let handler = handler_lock.lock().await;
+        (handler.queue().clone(), handler.current_channel())
+    };
+    let Some(np) = queue.current() else {
+        return respond(
+            ctx,
+            interaction,
+            Reply::Private("I'm not playing a song.".into()),
+        )
+        .await;
+    };
+
+    let is_dj = match &interaction.member {
+        Some(member) => is_dj(
+            &ctx.cache,
+            guild_id,
+            member,
+            data.settings.get(guild_id).dj_role,
+        ),
+        None => false,
+    };
+    let title = TrackInfo::of(&np).title;
+    let user = interaction.user.id;
+
+    let reply = match control {
+        Control::Pause => {
+            let paused = np.get_info().await?.playing == PlayMode::Pause;
+            if paused {
+                queue.resume()?;
+            } else {
+                queue.pause()?;
+            }
+            refresh_panel(
+                ctx,
+                interaction,
+                !paused,
+                data.guilds.with(guild_id, |g| g.loop_mode),
+            )
+            .await;
+            if paused {
+                Reply::Public(format!("Resumed *{title}*."))
+            } else {
+                Reply::Public(format!("Paused *{title}*."))
+            }
+        }
+        Control::Skip if is_dj || requester(&np) == Some(user) => {
+            skip_songs(&queue, 1);
+            Reply::Public(format!("Skipped *{title}*."))
+        }
+        Control::Skip => {
+            let channel = channel.map(|channel| ChannelId(channel.0));
+            vote_to_skip(ctx, &data.guilds, guild_id, channel, &queue, &np, user)
+        }
+        // Stopping drops everyone's songs, so only DJs may do it unless they're all yours.
+        Control::Stop
+            if !is_dj
+                && queue
+                    .current_queue()
+                    .iter()
+                    .any(|track| requester(track) != Some(user)) =>
+        {
+            Reply::Private("Only DJs can stop other people's songs.".into())
+        }
+        Control::Stop => {
+            queue.stop();
+            data.snapshots
+                .save(&handler_lock, guild_id, &data.guilds, &data.settings)
+                .await;
+            disable_panel(&ctx.http, &data.guilds, guild_id).await;
+            Reply::Public("Stopped playing and cleared the queue.".into())
+        }
+        Control::Loop => {
+            let mode = data.guilds.with(guild_id, |g| {
+                g.loop_mode = g.loop_mode.next();
+                g.loop_mode
+            });
+            loop_tracks(&queue, mode);
+            let paused = np.get_info().await?.playing == PlayMode::Pause;
+            refresh_panel(ctx, interaction, paused, mode).await;
+            Reply::Public(loop_message(mode).into())
+        }
+        Control::Shuffle if queue.len() < 2 => {
+            Reply::Private("There are no songs to shuffle.".into())
+        }
+        Control::Shuffle => {
+            queue.modify_queue(|q| q.make_contiguous()[1..].shuffle(&mut thread_rng()));
+            data.snapshots
+                .save(&handler_lock, guild_id, &data.guilds, &data.settings)
+                .await;
+            Reply::Public("Shuffled the queue.".into())
+        }
+    };
+
+    if let Reply::Public(content) = &reply {
+        debug!(
+            "{content} ({control:?} pressed by {user} in {}.)",
+            guild_id
+                .name(&ctx.cache)
+                .unwrap_or_else(|| guild_id.to_string())
+        );
+    }
+    respond(ctx, interaction, reply).await
+}
+
+/// Count `user`'s vote to skip `np`, skipping it once enough listeners agree.
+fn vote_to_skip(
+    ctx: &SerenityContext,
+    guilds: &GuildStore,
+    guild_id: GuildId,
+    channel: Option<ChannelId>,
+    queue: &TrackQueue,
+    np: &TrackHandle,
+    user: UserId,
+) -> Reply {
+    let title = TrackInfo::of(np).title;
+    let Some(channel) = channel else {
+        return Reply::Private("I'm not in a voice channel.".into());
+    };
+    if user_channel(&ctx.cache, guild_id, user) != Some(channel) {
+        return Reply::Private("You need to be in my voice channel to vote.".into());
+    }
+
+    let (new_vote, votes) = guilds.with(guild_id, |g| g.vote_skip(np.uuid(), user));
+    if !new_vote {
+        return Reply::Private("You've already voted.".into());
+    }
+
+    let required = required_skip_votes(count_listeners(&ctx.cache, guild_id, channel));
+    if votes >= required {
+        skip_voted(queue, np);
+        Reply::Public(format!("Skipped *{title}*."))
+    } else {
+        Reply::Public(vote_message(&title, votes, required))
+    }
+}
+
+async fn respond(
+    ctx: &SerenityContext,
+    interaction: &MessageComponentInteraction,
+    reply: Reply,
+) -> Result<()> {
+    let (content, ephemeral) = match reply {
+        Reply::Public(content) => (content, false),
+        Reply::Private(content) => (content, true),
+    };
+    interaction
+        .create_interaction_response(&ctx.http, |r| {
+            r.interaction_response_data(|d| d.content(content).ephemeral(ephemeral))
+        })
+        .await?;
+    Ok(())
+}
+
+/// Update the panel's buttons to show the current pause and loop state.
+async fn refresh_panel(
+    ctx: &SerenityContext,
+    interaction: &MessageComponentInteraction,
+    paused: bool,
+    loop_mode: LoopMode,
+) {
+    let mut message: Message = interaction.message.clone();
+    if let Err(e) = message
+        .edit(&ctx.http, |m| {
+            m.components(|c| create_now_playing_components(c, paused, loop_mode, false))
+        })
+        .await
+    {
+        error!("Error while updating now playing panel: {e}");
+    }
+}
+
+/// Disable the buttons on `guild_id`'s latest now-playing message, if it has one.
+pub(crate) async fn disable_panel(http: &Http, guilds: &GuildStore, guild_id: GuildId) {
+    let Some((channel, message)) = guilds.with(guild_id, |g| g.now_playing_panel.take()) else {
+        return;
+    };
+
+    if let Err(e) = channel
+        .edit_message(http, message, |m| {
+            m.components(|c| create_now_playing_components(c, false, LoopMode::Off, true))
+        })
+        .await
+    {
+        error!("Error while disabling now playing panel: {e}");
+    }
+}
@@ -1,33 +1,54 @@
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
-use log::{error, trace};
+use chrono::Utc;
+use log::{debug, error, trace};
 use poise::{
     async_trait,
-    serenity_prelude::{Cache, ChannelId, Http},
+    serenity_prelude::{Cache, Context as SerenityContext, GuildId, Http},
 };
-use songbird::{Event, EventContext, EventHandler};
+use songbird::{tracks::PlayMode, Call, Event, EventContext, EventHandler, Songbird, TrackEvent};
+use tokio::sync::Mutex;
 
-use crate::format::song_embed;
+use crate::{
+    controls::disable_panel,
+    filters::GuildFilters,
+    format::{create_now_playing_components, song_embed},
+    idle::{IdleTimeout, IDLE_CHECK_PERIOD},
+    local::song_input,
+    prefetch::{Prefetcher, PREFETCH_CHECK_PERIOD},
+    settings::SettingsStore,
+    snapshot::{QueueSnapshotter, SNAPSHOT_PERIOD},
+    state::{GuildStore, LoopMode, PlayedSong},
+    track::{requester, TrackInfo},
+    types::Data,
+};
 
+/// Announces each track as it starts in the guild's announce channel.
 pub(crate) struct NowPlaying {
     cache: Arc<Cache>,
-    channel: ChannelId,
+    guilds: GuildStore,
+    guild_id: GuildId,
     guild_name: String,
     http: Arc<Http>,
+    settings: SettingsStore,
 }
 
 impl NowPlaying {
     pub(crate) fn new(
         cache: Arc<Cache>,
-        channel: ChannelId,
+        guilds: GuildStore,
+        guild_id: GuildId,
         guild_name: String,
         http: Arc<Http>,
+        settings: SettingsStore,
     ) -> Self {
         Self {
             cache,
-            channel,
+            guilds,
+            guild_id,
             guild_name,
             http,
+            settings,
         }
     }
 }
@@ -39,28 +60,196 @@ impl EventHandler for NowPlaying {
             return None;
         };
 
-        let metadata = handle.metadata();
-        let title = metadata.title.as_ref().unwrap();
+        // Songbird fires this on every unpause too.
+        let announced = self
+            .guilds
+            .with(self.guild_id, |g| g.announced_track.replace(handle.uuid()));
+        if announced == Some(handle.uuid()) {
+            return None;
+        }
+
+        let song = TrackInfo::of(handle);
+        let title = &song.title;
+        let volume = handle.get_info().await.map_or(1.0, |info| info.volume);
 
         trace!("Now playing `{}` in {}.", title, self.guild_name);
 
-        if let Err(e) = self
-            .channel
+        // Only the latest panel's buttons work, so don't leave the old ones looking usable.
+        disable_panel(&self.http, &self.guilds, self.guild_id).await;
+
+        let (channel, loop_mode) = self
+            .guilds
+            .with(self.guild_id, |g| (g.announce_channel, g.loop_mode));
+        let channel = channel?;
+        let colour = self.settings.get(self.guild_id).embed_colour();
+        match channel
             .send_message(&self.http, |m| {
                 m.content(format!("Now playing *{title}*."))
-                    .embed(|e| song_embed(e, metadata))
+                    .embed(|e| song_embed(e, colour, &song, requester(handle), volume))
+                    .components(|c| create_now_playing_components(c, false, loop_mode, false))
             })
             .await
         {
-            error!(
+            Ok(message) => self.guilds.with(self.guild_id, |g| {
+                g.now_playing_panel = Some((channel, message.id));
+            }),
+            Err(e) => error!(
                 "Error sending `Now Playing` notification in {}: {e}",
-                self.channel
+                channel
                     .name(&self.cache)
                     .await
-                    .unwrap_or_else(|| self.channel.to_string())
-            );
+                    .unwrap_or_else(|| channel.to_string())
+            ),
+        }
+
+        None
+    }
+}
+
+/// Re-enqueues finished tracks while the guild is looping its queue.
+pub(crate) struct QueueLoop {
+    call: Weak<Mutex<Call>>,
+    guild_id: GuildId,
+    guilds: GuildStore,
+    filters: GuildFilters,
+}
+
+impl QueueLoop {
+    pub(crate) fn new(call: Weak<Mutex<Call>>, guild_id: GuildId, data: &Data) -> Self {
+        Self {
+            call,
+            guild_id,
+            guilds: data.guilds.clone(),
+            filters: GuildFilters::new(data, guild_id),
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for QueueLoop {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(state, handle)]) = ctx else {
+            return None;
         };
 
+        // Skipped and removed tracks are stopped rather than ended, and shouldn't come back.
+        if state.playing != PlayMode::End
+            || self.guilds.with(self.guild_id, |g| g.loop_mode) != LoopMode::Queue
+        {
+            return None;
+        }
+
+        let url = handle.metadata().source_url.clone()?;
+        let requester = requester(handle);
+        let song = match song_input(url, self.filters.clone()).await {
+            Ok(song) => song,
+            Err(e) => {
+                error!("Error while re-enqueuing looped track: {e}");
+                return None;
+            }
+        };
+
+        let call = self.call.upgrade()?;
+        let (track, _) = self
+            .guilds
+            .with(self.guild_id, |g| g.create_track(song, requester));
+        call.lock().await.enqueue(track);
+
+        debug!("Re-enqueued looped track in {}.", self.guild_id);
+
+        None
+    }
+}
+
+/// Records tracks in the guild's history once they finish or are skipped.
+pub(crate) struct History {
+    guild_id: GuildId,
+    guilds: GuildStore,
+}
+
+impl History {
+    pub(crate) fn new(guild_id: GuildId, guilds: GuildStore) -> Self {
+        Self { guild_id, guilds }
+    }
+}
+
+#[async_trait]
+impl EventHandler for History {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(&[(state, handle)]) = ctx else {
+            return None;
+        };
+
+        // Tracks removed before they started were never played.
+        if state.play_time.is_zero() {
+            return None;
+        }
+
+        let metadata = handle.metadata();
+        let song = PlayedSong {
+            title: metadata.title.clone()?,
+            url: metadata.source_url.clone()?,
+            requester: requester(handle),
+            played_at: Utc::now(),
+        };
+        self.guilds.with(self.guild_id, |g| g.remember_played(song));
+
         None
     }
 }
+
+/// Register the handlers every call needs on a newly joined `call`.
+pub(crate) fn add_call_events(
+    call: &mut Call,
+    call_lock: &Arc<Mutex<Call>>,
+    manager: Arc<Songbird>,
+    ctx: &SerenityContext,
+    data: &Data,
+    guild_id: GuildId,
+) {
+    call.add_global_event(
+        Event::Track(TrackEvent::Play),
+        NowPlaying::new(
+            ctx.cache.clone(),
+            data.guilds.clone(),
+            guild_id,
+            guild_id
+                .name(&ctx.cache)
+                .unwrap_or_else(|| guild_id.to_string()),
+            ctx.http.clone(),
+            data.settings.clone(),
+        ),
+    );
+    call.add_global_event(
+        Event::Track(TrackEvent::End),
+        QueueLoop::new(Arc::downgrade(call_lock), guild_id, data),
+    );
+    call.add_global_event(
+        Event::Track(TrackEvent::End),
+        History::new(guild_id, data.guilds.clone()),
+    );
+    call.add_global_event(
+        Event::Periodic(IDLE_CHECK_PERIOD, None),
+        IdleTimeout::new(
+            manager,
+            ctx.http.clone(),
+            data.guilds.clone(),
+            data.snapshots.clone(),
+            guild_id,
+        ),
+    );
+    call.add_global_event(
+        Event::Periodic(PREFETCH_CHECK_PERIOD, None),
+        Prefetcher::new(Arc::downgrade(call_lock), guild_id, data),
+    );
+    for event in [
+        Event::Track(TrackEvent::Play),
+        Event::Track(TrackEvent::End),
+        Event::Periodic(SNAPSHOT_PERIOD, None),
+    ] {
+        call.add_global_event(
+            event,
+            QueueSnapshotter::new(Arc::downgrade(call_lock), guild_id, data),
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use std::{process::Child, time::Duration};
+
+use poise::{serenity_prelude::GuildId, ChoiceParameter};
+use songbird::input::Input;
+
+use crate::{
+    cache::AudioCache,
+    loudness::{normalize_filter, LoudnessStore},
+    settings::SettingsStore,
+    state::GuildStore,
+    types::Data,
+    ytdl::from_beginning,
+};
+
+/// Centre frequencies of the equalizer's bands, in Hz.
+pub(crate) const EQ_BANDS: [u32; 10] = [31, 62, 125, 250, 500, 1000, 2000, 4000, 8000, 16000];
+/// Largest boost or cut of an equalizer band, in dB.
+pub(crate) const MAX_EQ_GAIN: i8 = 20;
+
+/// Audio effects applied to a guild's tracks through ffmpeg.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Filters {
+    /// Playback speed percentage, without changing pitch.
+    pub(crate) speed: Option<u16>,
+    /// Pitch percentage, without changing speed.
+    pub(crate) pitch: Option<u16>,
+    /// Bass gain in dB.
+    pub(crate) bass_boost: Option<u8>,
+    /// Gain of each of the [`EQ_BANDS`] in dB.
+    pub(crate) equalizer: Option<[i8; 10]>,
+    /// Cancel out sound panned to the centre, which is usually the vocals.
+    pub(crate) karaoke: bool,
+    /// Slowly pan the sound around the listener.
+    pub(crate) eight_d: bool,
+}
+
+impl Filters {
+    /// How many times faster than normal songs play.
+    pub(crate) fn speed_factor(&self) -> f64 {
+        f64::from(self.speed.unwrap_or(100)) / 100.0
+    }
+
+    /// The ffmpeg `-af` filter chain for these filters, if there are any.
+    pub(crate) fn chain(&self) -> Option<String> {
+        let mut filters = vec![];
+
+        let speed = self.speed_factor();
+        let pitch = f64::from(self.pitch.unwrap_or(100)) / 100.0;
+        if pitch != 1.0 {
+            // Resampling changes pitch and speed together, so the tempo makes up the difference.
+            filters.push("aresample=48000".to_string());
+            filters.push(format!("asetrate={:.0}", 48000.0 * pitch));
+            filters.push("aresample=48000".to_string());
+        }
+        filters.extend(atempo(speed / pitch));
+
+        if let Some(gain) = self.bass_boost.filter(|&gain| gain > 0) {
+            filters.push(format!("bass=g={gain}:f=110:w=0.6"));
+        }
+
+        if let Some(gains) = &self.equalizer {
+            for (freq, gain) in EQ_BANDS.iter().zip(gains) {
+                if *gain != 0 {
+                    filters.push(format!("equalizer=f={freq}:t=o:w=1:g={gain}"));
+                }
+            }
+        }
+
+        if self.karaoke {
+            filters.push("pan=stereo|c0=c0-c1|c1=c1-c0".to_string());
+        }
+
+        if self.eight_d {
+            filters.push("apulsator=hz=0.125".to_string());
+        }
+
+        (!filters.is_empty()).then(|| filters.join(","))
+    }
+
+    /// A short summary like `Speed 125% • Bass +10 dB`, if any filters are on.
+    pub(crate) fn describe(&self) -> Option<String> {
+        let mut parts = vec![];
+        if let Some(speed) = self.speed.filter(|&speed| speed != 100) {
+            parts.push(format!("Speed {speed}%"));
+        }
+        if let Some(pitch) = self.pitch.filter(|&pitch| pitch != 100) {
+            parts.push(format!("Pitch {pitch}%"));
+        }
+        if let Some(gain) = self.bass_boost.filter(|&gain| gain > 0) {
+            parts.push(format!("Bass +{gain} dB"));
+        }
+        if let Some(gains) = &self.equalizer {
+            parts.push(format!(
+                "EQ {}",
+                gains
+                    .iter()
+                    .map(|gain| format!("{gain:+}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+        }
+        if self.karaoke {
+            parts.push("Karaoke".to_string());
+        }
+        if self.eight_d {
+            parts.push("8D".to_string());
+        }
+
+        (!parts.is_empty()).then(|| format!("🎛 {}", parts.join(" • ")))
+    }
+}
+
+/// `atempo` filters for a tempo change of `factor`, split up to stay within each one's range.
+fn atempo(mut factor: f64) -> Vec<String> {
+    let mut filters = vec![];
+    while factor > 2.0 {
+        filters.push("atempo=2".to_string());
+        factor /= 2.0;
+    }
+    while factor < 0.5 {
+        filters.push("atempo=0.5".to_string());
+        factor /= 0.5;
+    }
+    if (factor - 1.0).abs() > f64::EPSILON {
+        filters.push(format!("atempo={factor:.4}"));
+    }
+    filters
+}
+
+/// Parse ten space or comma separated equalizer gains, like `3 2 0 0 -1 0 0 1 2 3`.
+pub(crate) fn parse_equalizer(s: &str) -> Option<[i8; 10]> {
+    let gains = s
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            part.trim_start_matches('+')
+                .parse::<i8>()
+                .ok()
+                .filter(|gain| (-MAX_EQ_GAIN..=MAX_EQ_GAIN).contains(gain))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    gains.try_into().ok()
+}
+
+/// A ready-made combination of filters.
+#[derive(ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Preset {
+    #[name = "nightcore"]
+    Nightcore,
+    #[name = "vaporwave"]
+    Vaporwave,
+    #[name = "bass_boost"]
+    BassBoost,
+    #[name = "karaoke"]
+    Karaoke,
+    #[name = "8d"]
+    EightD,
+}
+
+impl Preset {
+    pub(crate) fn filters(self) -> Filters {
+        match self {
+            Self::Nightcore => Filters {
+                speed: Some(125),
+                pitch: Some(125),
+                ..Default::default()
+            },
+            Self::Vaporwave => Filters {
+                speed: Some(80),
+                pitch: Some(80),
+                ..Default::default()
+            },
+            Self::BassBoost => Filters {
+                bass_boost: Some(10),
+                ..Default::default()
+            },
+            Self::Karaoke => Filters {
+                karaoke: true,
+                ..Default::default()
+            },
+            Self::EightD => Filters {
+                eight_d: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// The ffmpeg filters for one run of a song.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FilterChain {
+    pub(crate) filters: Option<String>,
+    /// Whether ffmpeg will print the song's loudness to stderr once it reaches the end.
+    pub(crate) measures_loudness: bool,
+}
+
+/// A guild's filters and loudness normalization, as of whenever one of its tracks starts or restarts.
+#[derive(Clone, Debug)]
+pub(crate) struct GuildFilters {
+    guilds: GuildStore,
+    settings: SettingsStore,
+    loudness: LoudnessStore,
+    /// Where tracks are played from instead of the network, when they've been downloaded.
+    pub(crate) cache: Option<AudioCache>,
+    guild_id: GuildId,
+}
+
+impl GuildFilters {
+    pub(crate) fn new(data: &Data, guild_id: GuildId) -> Self {
+        Self {
+            guilds: data.guilds.clone(),
+            settings: data.settings.clone(),
+            loudness: data.loudness.clone(),
+            cache: data.cache.clone(),
+            guild_id,
+        }
+    }
+
+    /// The filters for the song at `url`, played from `start` if given.
+    pub(crate) fn chain(&self, url: &str, start: Option<Duration>) -> FilterChain {
+        let mut filters = vec![];
+        let mut measures_loudness = false;
+
+        // Normalize first, so that's what gets measured rather than the other filters.
+        if self.settings.get(self.guild_id).normalize_loudness() {
+            let loudness = self.loudness.get(url);
+            filters.push(normalize_filter(loudness));
+            // Only a run through the whole song measures all of it.
+            measures_loudness = loudness.is_none() && from_beginning(start);
+        }
+        filters.extend(self.guilds.with(self.guild_id, |g| g.filters.chain()));
+
+        FilterChain {
+            filters: (!filters.is_empty()).then(|| filters.join(",")),
+            measures_loudness,
+        }
+    }
+
+    /// Where a restart of the song at `url` from `time` should really start, if it's being
+    /// restarted by [`GuildStore::restart_track`](crate::state::GuildStore::restart_track).
+    pub(crate) fn restart_time(&self, url: &str, time: Option<Duration>) -> Option<Duration> {
+        let restart = self.guilds.with(self.guild_id, |g| {
+            g.restart_from
+                .take_if(|(restart_url, _)| restart_url == url)
+        });
+        restart.map(|(_, position)| position).or(time)
+    }
+
+    /// The input prefetched for `url`, if it's ready and was started with the current filters.
+    pub(crate) fn take_prefetched(&self, url: &str) -> Option<Input> {
+        let filters = self.chain(url, None).filters;
+        let prefetched = self.guilds.with(self.guild_id, |g| {
+            g.prefetched
+                .take_if(|prefetched| prefetched.url == url && prefetched.input.is_some())
+        })?;
+        (prefetched.filters == filters)
+            .then_some(prefetched.input)
+            .flatten()
+    }
+
+    /// Cache the loudness `ffmpeg` measures for `url`, if `chain` has it measure any.
+    pub(crate) fn record_loudness(&self, url: &str, chain: &FilterChain, ffmpeg: &mut Child) {
+        if !chain.measures_loudness {
+            return;
+        }
+        if let Some(stderr) = ffmpeg.stderr.take() {
+            self.loudness.record(url.to_string(), stderr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atempo_splits_large_changes() {
+        assert!(atempo(1.0).is_empty());
+        assert_eq!(atempo(1.25), ["atempo=1.2500"]);
+        assert_eq!(atempo(3.0), ["atempo=2", "atempo=1.5000"]);
+        assert_eq!(atempo(4.0), ["atempo=2", "atempo=2.0000"]);
+        assert_eq!(atempo(0.25), ["atempo=0.5", "atempo=0.5000"]);
+        assert_eq!(atempo(0.2), ["atempo=0.5", "atempo=0.5", "atempo=0.8000"]);
+    }
+
+    #[test]
+    fn parses_equalizer_gains() {
+        assert_eq!(
+            parse_equalizer("3 2 0 0 -1 0 0 1 2 3"),
+            Some([3, 2, 0, 0, -1, 0, 0, 1, 2, 3])
+        );
+        assert_eq!(
+            parse_equalizer(" +3, 2,0 ,0,-1 0\t0 1 2 3 "),
+            Some([3, 2, 0, 0, -1, 0, 0, 1, 2, 3])
+        );
+        assert_eq!(
+            parse_equalizer("20 -20 0 0 0 0 0 0 0 0"),
+            Some([20, -20, 0, 0, 0, 0, 0, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_gains() {
+        for gains in [
+            "21 0 0 0 0 0 0 0 0 0",
+            "0 0 0 0 0 0 0 0 0 -21",
+            "-128 0 0 0 0 0 0 0 0 0",
+            "128 0 0 0 0 0 0 0 0 0",
+        ] {
+            assert_eq!(parse_equalizer(gains), None, "{gains}");
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_gain_counts() {
+        for gains in ["", "0 0 0 0 0 0 0 0 0", "0 0 0 0 0 0 0 0 0 0 0"] {
+            assert_eq!(parse_equalizer(gains), None, "{gains:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_gains() {
+        for gains in [
+            "a 0 0 0 0 0 0 0 0 0",
+            "1.5 0 0 0 0 0 0 0 0 0",
+            "0;0;0;0;0;0;0;0;0;0",
+        ] {
+            assert_eq!(parse_equalizer(gains), None, "{gains}");
+        }
+    }
+
+    #[test]
+    fn speed_and_pitch_combine_into_tempo() {
+        let filters = Filters {
+            speed: Some(150),
+            pitch: Some(150),
+            ..Default::default()
+        };
+        assert_eq!(
+            filters.chain().as_deref(),
+            Some("aresample=48000,asetrate=72000,aresample=48000")
+        );
+        assert_eq!(filters.speed_factor(), 1.5);
+        assert_eq!(Filters::default().chain(), None);
+    }
+}
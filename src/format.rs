@@ -1,62 +1,199 @@
-use std::{collections::VecDeque, time::Duration};
+use std::{cmp::Reverse, collections::VecDeque, time::Duration};
 
-use chrono::NaiveDate;
 use poise::{
-    serenity_prelude::{ButtonStyle, CreateComponents, CreateEmbed, EditMessage, User},
+    serenity_prelude::{
+        ButtonStyle, CreateComponents, CreateEmbed, EditMessage, Mentionable, User, UserId,
+    },
     CreateReply,
 };
-use songbird::{input::Metadata, tracks::TrackHandle};
+use songbird::tracks::{PlayMode, TrackHandle, TrackState};
 
-use crate::types::PAGE_SIZE;
+use crate::{
+    cache::CachedSong,
+    filters::Filters,
+    playlists::{PlaylistOwner, SavedPlaylist},
+    settings::GuildSettings,
+    state::{LoopMode, PlayedSong},
+    track::{requester, TrackInfo},
+    ytdl::{is_url, Playlist},
+};
+
+/// `title` linked to `url`, unless it's somewhere only the bot can open, like a local file.
+pub(crate) fn song_link(title: &str, url: Option<&str>) -> String {
+    match url.filter(|url| is_url(url)) {
+        Some(url) => format!("[{title}]({url})"),
+        None => title.to_string(),
+    }
+}
 
 pub(crate) fn format_duration(duration: &Duration) -> String {
     let secs = duration.as_secs();
-    let mins = secs / 60;
+    let hours = secs / 3600;
+    let mins = secs / 60 % 60;
     let secs = secs % 60;
-    format!("{mins}:{secs:02}")
+    if hours > 0 {
+        format!("{hours}:{mins:02}:{secs:02}")
+    } else {
+        format!("{mins}:{secs:02}")
+    }
 }
 
-pub(crate) fn base_embed(e: &mut CreateEmbed) -> &mut CreateEmbed {
-    e.color(0x0789f0)
+/// A position within a song, either absolute or relative to the current position.
+pub(crate) enum Timestamp {
+    Absolute(Duration),
+    Forward(Duration),
+    Backward(Duration),
 }
 
-pub(crate) fn song_embed<'e>(mut e: &'e mut CreateEmbed, song: &Metadata) -> &'e mut CreateEmbed {
-    e = base_embed(e);
+impl Timestamp {
+    /// Resolve this timestamp against the current position.
+    pub(crate) fn resolve(&self, current: Duration) -> Duration {
+        match self {
+            Self::Absolute(position) => *position,
+            Self::Forward(offset) => current.saturating_add(*offset),
+            Self::Backward(offset) => current.saturating_sub(*offset),
+        }
+    }
+}
 
-    if let Some(title) = &song.title {
-        e = e.title(title);
+/// Parse a duration such as `1:23`, `1:02:03`, `90`, `90s` or `1h2m3s`.
+///
+/// Units go from largest to smallest, each at most once. Durations too long to count are `None`,
+/// but anything up to `u64::MAX` seconds is accepted, so callers must clamp it to what they allow.
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+
+    if s.contains(':') {
+        let parts = s
+            .split(':')
+            .map(|part| part.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let (first, rest) = parts.split_first()?;
+        if parts.len() > 3 || rest.iter().any(|&part| part >= 60) {
+            return None;
+        }
+        let secs = rest
+            .iter()
+            .try_fold(*first, |acc, &part| acc.checked_mul(60)?.checked_add(part))?;
+        return Some(Duration::from_secs(secs));
+    }
+
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse().ok().map(Duration::from_secs);
     }
 
-    if let Some(url) = &song.source_url {
+    let mut secs: u64 = 0;
+    let mut digits = String::new();
+    let mut last_multiplier = u64::MAX;
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let multiplier = match c.to_ascii_lowercase() {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        if multiplier >= last_multiplier {
+            return None;
+        }
+        last_multiplier = multiplier;
+        secs = secs.checked_add(digits.parse::<u64>().ok()?.checked_mul(multiplier)?)?;
+        digits.clear();
+    }
+    // Digits after a unit would need their own, like the 5 in `90s5`.
+    if !digits.is_empty() {
+        return None;
+    }
+
+    Some(Duration::from_secs(secs))
+}
+
+/// Parse a timestamp, treating a leading `+` or `-` as relative to the current position.
+pub(crate) fn parse_timestamp(s: &str) -> Option<Timestamp> {
+    let s = s.trim();
+    if let Some(offset) = s.strip_prefix('+') {
+        parse_duration(offset).map(Timestamp::Forward)
+    } else if let Some(offset) = s.strip_prefix('-') {
+        parse_duration(offset).map(Timestamp::Backward)
+    } else {
+        parse_duration(s).map(Timestamp::Absolute)
+    }
+}
+
+/// Parse a hex colour like `#0789f0`.
+pub(crate) fn parse_colour(s: &str) -> Option<u32> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+pub(crate) fn base_embed(e: &mut CreateEmbed, colour: u32) -> &mut CreateEmbed {
+    e.color(colour)
+}
+
+pub(crate) fn format_volume(volume: f32) -> String {
+    format!("🔊 {:.0}%", volume * 100.0)
+}
+
+/// A byte count in megabytes, like `12.3 MB`.
+pub(crate) fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+pub(crate) fn song_embed<'e>(
+    e: &'e mut CreateEmbed,
+    colour: u32,
+    song: &TrackInfo,
+    requester: Option<UserId>,
+    volume: f32,
+) -> &'e mut CreateEmbed {
+    song_embed_with_footer(e, colour, song, requester, volume, vec![])
+}
+
+pub(crate) fn song_embed_with_footer<'e>(
+    mut e: &'e mut CreateEmbed,
+    colour: u32,
+    song: &TrackInfo,
+    requester: Option<UserId>,
+    volume: f32,
+    extra_footer: Vec<String>,
+) -> &'e mut CreateEmbed {
+    e = base_embed(e, colour).title(&song.title);
+
+    if let Some(url) = &song.url {
         e = e.url(url);
     }
 
-    if let Some(artist) = &song.artist {
-        e = e.author(|a| a.name(artist));
-    } else if let Some(channel) = &song.channel {
-        e = e.author(|a| a.name(channel));
+    if let Some(author) = &song.author {
+        e = e.author(|a| a.name(author));
     }
 
     if let Some(url) = &song.thumbnail {
         e = e.image(url);
     }
 
-    let mut footer = vec![];
-
-    if let Some(duration) = &song.duration {
-        footer.push(format_duration(duration));
+    if let Some(user) = requester {
+        e = e.description(format!("Requested by {}", user.mention()));
     }
 
-    if let Some(date) = song.date.as_ref().and_then(|d| {
-        let Some(year) = d.get(0..4).and_then(|s| s.parse().ok()) else { return None; };
-        let Some(month) = d.get(4..6).and_then(|s| s.parse().ok()) else { return None; };
-        let Some(day) = d.get(6..8).and_then(|s| s.parse().ok()) else { return None; };
-        let Some(date) = NaiveDate::from_ymd_opt(year, month, day) else { return None; };
-        Some(date.format("Uploaded on %Y/%m/%d"))
-    }) {
-        footer.push(date.to_string());
+    let mut footer = vec![song.duration_text()];
+
+    if let Some(date) = &song.upload_date {
+        footer.push(date.format("Uploaded on %Y/%m/%d").to_string());
     }
 
+    footer.push(format_volume(volume));
+    footer.extend(extra_footer);
+
     if !footer.is_empty() {
         e = e.footer(|f| f.text(footer.join(" • ")));
     }
@@ -64,65 +201,159 @@ pub(crate) fn song_embed<'e>(mut e: &'e mut CreateEmbed, song: &Metadata) -> &'e
     e
 }
 
+pub(crate) fn playlist_embed<'e>(
+    mut e: &'e mut CreateEmbed,
+    colour: u32,
+    playlist: &Playlist,
+    volume: f32,
+) -> &'e mut CreateEmbed {
+    e = base_embed(e, colour);
+
+    if let Some(url) = &playlist.url {
+        e = e.url(url);
+    }
+
+    if let Some(title) = &playlist.title {
+        e = e.title(title);
+    }
+
+    if let Some(url) = playlist
+        .entries
+        .iter()
+        .find_map(|song| song.thumbnail.as_ref())
+    {
+        e = e.image(url);
+    }
+
+    let total: Duration = playlist
+        .entries
+        .iter()
+        .filter_map(|song| song.duration)
+        .sum();
+    e.footer(|f| {
+        f.text(format!(
+            "{} songs • {} • {}",
+            playlist.entries.len(),
+            format_duration(&total),
+            format_volume(volume)
+        ))
+    })
+}
+
+/// Number of segments in a progress bar.
+const PROGRESS_BAR_LEN: usize = 12;
+
+/// A bar like `▬▬▬🔘▬▬▬ 1:23 / 4:56` showing how far through a song `position` is.
+pub(crate) fn progress_bar(position: &Duration, duration: Option<&Duration>) -> String {
+    let Some(duration) = duration.filter(|duration| !duration.is_zero()) else {
+        return format!("🔘 {}", format_duration(position));
+    };
+
+    let progress = (position.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0);
+    let knob = ((progress * PROGRESS_BAR_LEN as f64) as usize).min(PROGRESS_BAR_LEN - 1);
+    format!(
+        "{}🔘{} {} / {}",
+        "▬".repeat(knob),
+        "▬".repeat(PROGRESS_BAR_LEN - 1 - knob),
+        format_duration(position),
+        format_duration(duration)
+    )
+}
+
 pub(crate) fn now_playing_message<'m, 'att>(
     mut m: &'m mut CreateReply<'att>,
-    song: &Metadata,
+    colour: u32,
+    song: &TrackInfo,
+    requester: Option<UserId>,
+    state: &TrackState,
+    filters: &Filters,
 ) -> &'m mut CreateReply<'att> {
-    if let Some(title) = &song.title {
-        m = m.content(format!("Now playing *{title}*."));
-    } else {
-        m = m.content("Now playing a new song.");
+    m = m.content(format!("Now playing *{}*.", song.title));
+
+    let mut footer = vec![];
+    if state.playing == PlayMode::Pause {
+        footer.push("⏸ Paused".to_string());
     }
+    footer.extend(filters.describe());
+
+    m.embed(|e| {
+        song_embed_with_footer(e, colour, song, requester, state.volume, footer).field(
+            "Progress",
+            progress_bar(&state.position, song.duration.as_ref()),
+            false,
+        )
+    })
+}
 
-    m.embed(|e| song_embed(e, song))
+/// A queue line's song link and duration, followed by who requested it.
+fn queue_line(track: &TrackHandle) -> String {
+    let song = TrackInfo::of(track);
+    let mut line = format!("{} `{}`", song.link(), song.duration_text());
+    if let Some(user) = requester(track) {
+        line.push_str(&format!(" • {}", user.mention()));
+    }
+    line
 }
 
 fn create_queue_embed<'e>(
     mut e: &'e mut CreateEmbed,
+    settings: &GuildSettings,
     np: &TrackHandle,
     queue: &VecDeque<(usize, &TrackHandle)>,
     page: usize,
-    total_pages: usize,
+    loop_mode: LoopMode,
+    filters: &Filters,
 ) -> &'e mut CreateEmbed {
-    e = base_embed(e).title("Queue").field(
+    let total_pages = (queue.len() + 1).div_ceil(settings.page_size());
+    e = base_embed(e, settings.embed_colour()).title("Queue").field(
         "Now Playing",
-        format!(
-            "[{}]({}) `{}`",
-            np.metadata().title.as_ref().unwrap(),
-            np.metadata().source_url.as_ref().unwrap(),
-            format_duration(np.metadata().duration.as_ref().unwrap()),
-        ),
+        queue_line(np),
         false,
     );
 
+    let mut footer = vec![];
+
     if !queue.is_empty() {
-        e = e
-            .field(
-                format!("Page {}", page + 1),
-                queue
-                    .iter()
-                    .map(|(i, song)| {
-                        let metadata = song.metadata();
-                        format!(
-                            "*{i}.* [{title}]({url}) `{duration}`",
-                            title = metadata.title.as_ref().unwrap(),
-                            url = metadata.source_url.as_ref().unwrap(),
-                            duration = format_duration(metadata.duration.as_ref().unwrap())
-                        )
-                    })
-                    .skip(page * PAGE_SIZE)
-                    .take(PAGE_SIZE)
-                    .collect::<Vec<_>>()
-                    .join("\n"),
-                false,
-            )
-            .footer(|f| f.text(format!("{}/{}", page + 1, total_pages)));
+        e = e.field(
+            format!("Page {}", page + 1),
+            queue
+                .iter()
+                .map(|(i, song)| format!("*{i}.* {}", queue_line(song)))
+                .skip(page * settings.page_size())
+                .take(settings.page_size())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            false,
+        );
+        footer.push(format!("{}/{}", page + 1, total_pages));
+    }
+
+    match loop_mode {
+        LoopMode::Off => {}
+        LoopMode::Track => footer.push("🔂 Looping song".to_string()),
+        LoopMode::Queue => footer.push("🔁 Looping queue".to_string()),
+    }
+    footer.extend(filters.describe());
+
+    if !footer.is_empty() {
+        e = e.footer(|f| f.text(footer.join(" • ")));
     }
 
     e
 }
 
-fn create_queue_components(
+/// The page to show after pressing the page button `custom_id` on `page`.
+pub(crate) fn turn_page(custom_id: &str, page: usize) -> Option<usize> {
+    match custom_id {
+        "first" => Some(0),
+        "previous" => Some(page.saturating_sub(1)),
+        "next" => Some(page.saturating_add(1)),
+        "last" => Some(usize::MAX),
+        _ => None,
+    }
+}
+
+pub(crate) fn create_page_components(
     c: &mut CreateComponents,
     page: usize,
     total_pages: usize,
@@ -156,39 +387,371 @@ fn create_queue_components(
     })
 }
 
+pub(crate) fn create_now_playing_components(
+    c: &mut CreateComponents,
+    paused: bool,
+    loop_mode: LoopMode,
+    disabled: bool,
+) -> &mut CreateComponents {
+    c.create_action_row(|r| {
+        r.create_button(|b| {
+            b.custom_id("np_pause")
+                .emoji('⏯')
+                .style(if paused {
+                    ButtonStyle::Success
+                } else {
+                    ButtonStyle::Secondary
+                })
+                .disabled(disabled)
+        })
+        .create_button(|b| {
+            b.custom_id("np_skip")
+                .emoji('⏭')
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled)
+        })
+        .create_button(|b| {
+            b.custom_id("np_stop")
+                .emoji('⏹')
+                .style(ButtonStyle::Danger)
+                .disabled(disabled)
+        })
+        .create_button(|b| {
+            b.custom_id("np_loop")
+                .emoji(if loop_mode == LoopMode::Track {
+                    '🔂'
+                } else {
+                    '🔁'
+                })
+                .style(if loop_mode == LoopMode::Off {
+                    ButtonStyle::Secondary
+                } else {
+                    ButtonStyle::Success
+                })
+                .disabled(disabled)
+        })
+        .create_button(|b| {
+            b.custom_id("np_shuffle")
+                .emoji('🔀')
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled)
+        })
+    })
+}
+
+pub(crate) fn search_embed<'e>(
+    e: &'e mut CreateEmbed,
+    colour: u32,
+    query: &str,
+    results: &[TrackInfo],
+) -> &'e mut CreateEmbed {
+    base_embed(e, colour)
+        .title(format!("Results for \"{query}\""))
+        .description(
+            results
+                .iter()
+                .enumerate()
+                .map(|(i, song)| {
+                    let mut line =
+                        format!("*{}.* {} `{}`", i + 1, song.link(), song.duration_text());
+                    if let Some(author) = &song.author {
+                        line.push_str(&format!(" • {author}"));
+                    }
+                    line
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+}
+
+pub(crate) fn create_search_components<'c>(
+    c: &'c mut CreateComponents,
+    results: &[TrackInfo],
+    disabled: bool,
+) -> &'c mut CreateComponents {
+    c.create_action_row(|r| {
+        r.create_select_menu(|m| {
+            m.custom_id("song")
+                .placeholder("Choose a song")
+                .disabled(disabled)
+                .options(|o| {
+                    for (i, song) in results.iter().enumerate() {
+                        o.create_option(|opt| {
+                            opt.label(format!("{}. {}", i + 1, truncate(&song.title, 90)))
+                                .value(i)
+                                .description(song.duration_text())
+                        });
+                    }
+                    o
+                })
+        })
+    })
+}
+
+pub(crate) fn truncate(s: &str, max_chars: usize) -> String {
+    if s.chars().count() <= max_chars {
+        s.to_string()
+    } else {
+        let mut truncated: String = s.chars().take(max_chars - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
 pub(crate) fn queue_message<'m, 'att>(
     m: &'m mut CreateReply<'att>,
+    settings: &GuildSettings,
     queue: &[TrackHandle],
     page: usize,
+    loop_mode: LoopMode,
+    filters: &Filters,
     disabled: bool,
 ) -> (&'m mut CreateReply<'att>, usize) {
+    let page_size = settings.page_size();
     let mut queue: VecDeque<_> = queue.iter().enumerate().collect();
-    let total_pages = (queue.len() as f64 / PAGE_SIZE as f64).ceil() as usize;
+    let total_pages = (queue.len() as f64 / page_size as f64).ceil() as usize;
     let (_, np) = queue.pop_front().unwrap();
 
     let page = page.clamp(0, total_pages - 1);
 
     let m = m
-        .embed(|e| create_queue_embed(e, np, &queue, page, total_pages))
-        .components(|c| create_queue_components(c, page, total_pages, disabled));
+        .embed(|e| create_queue_embed(e, settings, np, &queue, page, loop_mode, filters))
+        .components(|c| create_page_components(c, page, total_pages, disabled));
 
     (m, page)
 }
 
 pub(crate) fn queue_message_edit<'m, 'att>(
     m: &'m mut EditMessage<'att>,
+    settings: &GuildSettings,
     queue: &[TrackHandle],
     page: usize,
+    loop_mode: LoopMode,
+    filters: &Filters,
 ) -> (&'m mut EditMessage<'att>, usize) {
+    let page_size = settings.page_size();
     let mut queue: VecDeque<_> = queue.iter().enumerate().collect();
-    let total_pages = (queue.len() as f32 / PAGE_SIZE as f32).ceil() as usize;
+    let total_pages = (queue.len() as f32 / page_size as f32).ceil() as usize;
     let (_, np) = queue.pop_front().unwrap();
 
     let page = page.clamp(0, total_pages - 1);
 
     let m = m
-        .embed(|e| create_queue_embed(e, np, &queue, page, total_pages))
-        .components(|c| create_queue_components(c, page, total_pages, false));
+        .embed(|e| create_queue_embed(e, settings, np, &queue, page, loop_mode, filters))
+        .components(|c| create_page_components(c, page, total_pages, false));
+
+    (m, page)
+}
+
+fn create_saved_playlist_embed<'e>(
+    mut e: &'e mut CreateEmbed,
+    settings: &GuildSettings,
+    playlist: &SavedPlaylist,
+    page: usize,
+    total_pages: usize,
+) -> &'e mut CreateEmbed {
+    e = base_embed(e, settings.embed_colour()).title(&playlist.name);
+
+    if playlist.songs.is_empty() {
+        e = e.description("This playlist is empty.");
+    } else {
+        e = e.description(
+            playlist
+                .songs
+                .iter()
+                .enumerate()
+                .skip(page * settings.page_size())
+                .take(settings.page_size())
+                .map(|(i, song)| {
+                    let mut line =
+                        format!("*{}.* {}", i + 1, song_link(&song.title, Some(&song.url)));
+                    if let Some(duration) = &song.duration {
+                        line.push_str(&format!(" `{}`", format_duration(duration)));
+                    }
+                    line
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+
+    let total: Duration = playlist.songs.iter().filter_map(|song| song.duration).sum();
+    e.footer(|f| {
+        f.text(format!(
+            "{}/{} • {} songs • {} • {}",
+            page + 1,
+            total_pages,
+            playlist.songs.len(),
+            format_duration(&total),
+            match playlist.owner {
+                PlaylistOwner::User(_) => "👤 Personal",
+                PlaylistOwner::Guild(_) => "🏠 Shared",
+            }
+        ))
+    })
+}
+
+pub(crate) fn saved_playlist_message<'m, 'att>(
+    m: &'m mut CreateReply<'att>,
+    settings: &GuildSettings,
+    playlist: &SavedPlaylist,
+    page: usize,
+    disabled: bool,
+) -> (&'m mut CreateReply<'att>, usize) {
+    let total_pages = playlist.songs.len().div_ceil(settings.page_size()).max(1);
+    let page = page.clamp(0, total_pages - 1);
+
+    let m = m
+        .embed(|e| create_saved_playlist_embed(e, settings, playlist, page, total_pages))
+        .components(|c| create_page_components(c, page, total_pages, disabled));
+
+    (m, page)
+}
+
+pub(crate) fn saved_playlist_message_edit<'m, 'att>(
+    m: &'m mut EditMessage<'att>,
+    settings: &GuildSettings,
+    playlist: &SavedPlaylist,
+    page: usize,
+) -> (&'m mut EditMessage<'att>, usize) {
+    let total_pages = playlist.songs.len().div_ceil(settings.page_size()).max(1);
+    let page = page.clamp(0, total_pages - 1);
+
+    let m = m
+        .embed(|e| create_saved_playlist_embed(e, settings, playlist, page, total_pages))
+        .components(|c| create_page_components(c, page, total_pages, false));
+
+    (m, page)
+}
+
+pub(crate) fn saved_playlists_embed<'e>(
+    e: &'e mut CreateEmbed,
+    colour: u32,
+    playlists: &[SavedPlaylist],
+) -> &'e mut CreateEmbed {
+    base_embed(e, colour).title("Playlists").description(
+        playlists
+            .iter()
+            .map(|playlist| {
+                format!(
+                    "{} **{}** • {} songs",
+                    match playlist.owner {
+                        PlaylistOwner::User(_) => "👤",
+                        PlaylistOwner::Guild(_) => "🏠",
+                    },
+                    playlist.name,
+                    playlist.songs.len()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+/// Number of most played songs listed by `/cache view`.
+const TOP_CACHED_SONGS: usize = 10;
+
+pub(crate) fn cache_embed<'e>(
+    e: &'e mut CreateEmbed,
+    colour: u32,
+    songs: &[CachedSong],
+    max_size: u64,
+) -> &'e mut CreateEmbed {
+    let size = songs.iter().map(|song| song.size).sum();
+    let mut top: Vec<_> = songs.iter().collect();
+    top.sort_by_key(|song| Reverse(song.plays));
+
+    base_embed(e, colour)
+        .title("Audio cache")
+        .field("Songs", songs.len().to_string(), true)
+        .field(
+            "Size",
+            format!("{} / {}", format_size(size), format_size(max_size)),
+            true,
+        )
+        .description(
+            top.iter()
+                .take(TOP_CACHED_SONGS)
+                .map(|song| {
+                    format!(
+                        "[{}]({}) • {} plays • {}",
+                        song.title,
+                        song.url,
+                        song.plays,
+                        format_size(song.size)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+}
+
+fn create_history_embed<'e>(
+    mut e: &'e mut CreateEmbed,
+    settings: &GuildSettings,
+    history: &[PlayedSong],
+    page: usize,
+    total_pages: usize,
+) -> &'e mut CreateEmbed {
+    e = base_embed(e, settings.embed_colour()).title("History");
+
+    e = e.description(
+        history
+            .iter()
+            .enumerate()
+            .skip(page * settings.page_size())
+            .take(settings.page_size())
+            .map(|(i, song)| {
+                let mut line = format!("*{}.* {}", i + 1, song_link(&song.title, Some(&song.url)));
+                if let Some(requester) = song.requester {
+                    line.push_str(&format!(" • {}", requester.mention()));
+                }
+                line.push_str(&format!(" • <t:{}:R>", song.played_at.timestamp()));
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+
+    e.footer(|f| {
+        f.text(format!(
+            "{}/{} • {} songs",
+            page + 1,
+            total_pages,
+            history.len()
+        ))
+    })
+}
+
+pub(crate) fn history_message<'m, 'att>(
+    m: &'m mut CreateReply<'att>,
+    settings: &GuildSettings,
+    history: &[PlayedSong],
+    page: usize,
+    disabled: bool,
+) -> (&'m mut CreateReply<'att>, usize) {
+    let total_pages = history.len().div_ceil(settings.page_size()).max(1);
+    let page = page.clamp(0, total_pages - 1);
+
+    let m = m
+        .embed(|e| create_history_embed(e, settings, history, page, total_pages))
+        .components(|c| create_page_components(c, page, total_pages, disabled));
+
+    (m, page)
+}
+
+pub(crate) fn history_message_edit<'m, 'att>(
+    m: &'m mut EditMessage<'att>,
+    settings: &GuildSettings,
+    history: &[PlayedSong],
+    page: usize,
+) -> (&'m mut EditMessage<'att>, usize) {
+    let total_pages = history.len().div_ceil(settings.page_size()).max(1);
+    let page = page.clamp(0, total_pages - 1);
+
+    let m = m
+        .embed(|e| create_history_embed(e, settings, history, page, total_pages))
+        .components(|c| create_page_components(c, page, total_pages, false));
 
     (m, page)
 }
@@ -196,3 +759,63 @@ pub(crate) fn queue_message_edit<'m, 'att>(
 pub(crate) fn format_user_for_log(user: &User) -> String {
     format!("{} [{}]", user.tag(), user.id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Option<Duration> {
+        Some(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), secs(90));
+        assert_eq!(parse_duration(" 90s "), secs(90));
+        assert_eq!(parse_duration("1:23"), secs(83));
+        assert_eq!(parse_duration("1:02:03"), secs(3723));
+        assert_eq!(parse_duration("1h2m3s"), secs(3723));
+        assert_eq!(parse_duration("1H30M"), secs(5400));
+        assert_eq!(parse_duration("10m"), secs(600));
+        assert_eq!(parse_duration("0"), secs(0));
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for s in [
+            "", " ", "s", "1x", "1:60", "1:2:3:4", "1::2", ":30", "90s5", "1h30", "30s1m", "1m1m",
+            "-5", "1.5m",
+        ] {
+            assert_eq!(parse_duration(s), None, "{s}");
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_durations() {
+        for s in [
+            "99999999999999999h",
+            "99999999999999999999",
+            "999999999999999999m",
+            "5124095576030431h59m59s",
+            "307445734561825861:00",
+            "5124095576030431:59:59",
+        ] {
+            assert_eq!(parse_duration(s), None, "{s}");
+        }
+        assert_eq!(
+            parse_duration(&u64::MAX.to_string()),
+            Some(Duration::from_secs(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let current = Duration::from_secs(60);
+        let resolve = |s| parse_timestamp(s).map(|timestamp| timestamp.resolve(current));
+        assert_eq!(resolve("1:30"), secs(90));
+        assert_eq!(resolve("+30s"), secs(90));
+        assert_eq!(resolve("-30"), secs(30));
+        assert_eq!(resolve("-2m"), secs(0));
+        assert_eq!(resolve("+90s5"), None);
+    }
+}
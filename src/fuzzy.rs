@@ -0,0 +1,70 @@
+/// Score how well `query` matches `candidate`, ignoring case.
+///
+/// Returns `None` unless every character of `query` appears in `candidate` in order. Higher
+/// scores are better: consecutive matches and matches at the start of words count for more.
+pub(crate) fn fuzzy_score(query: &str, candidate: &str) -> Option<usize> {
+    let query: Vec<char> = query.trim().to_lowercase().chars().collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let mut score = 0;
+    let mut matched = 0;
+    let mut previous_matched = false;
+    let mut previous = ' ';
+
+    for c in candidate.to_lowercase().chars() {
+        if matched < query.len() && c == query[matched] {
+            score += 1;
+            if previous_matched {
+                score += 3;
+            }
+            if !previous.is_alphanumeric() {
+                score += 2;
+            }
+            matched += 1;
+            previous_matched = true;
+        } else {
+            previous_matched = false;
+        }
+        previous = c;
+    }
+
+    (matched == query.len()).then_some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_in_order_ignoring_case() {
+        assert!(fuzzy_score("nggyu", "Never Gonna Give You Up").is_some());
+        assert!(fuzzy_score("GONNA", "never gonna give you up").is_some());
+        assert_eq!(fuzzy_score("", "Anything"), Some(0));
+        assert_eq!(fuzzy_score("  ", "Anything"), Some(0));
+    }
+
+    #[test]
+    fn rejects_non_matches() {
+        assert_eq!(fuzzy_score("xyz", "Never Gonna Give You Up"), None);
+        // Every character is there, but not in order.
+        assert_eq!(fuzzy_score("up never", "Never Gonna Give You Up"), None);
+        assert_eq!(fuzzy_score("songs", "song"), None);
+    }
+
+    #[test]
+    fn prefers_consecutive_matches() {
+        let consecutive = fuzzy_score("abc", "xabcx").unwrap();
+        let scattered = fuzzy_score("abc", "xaxbxcx").unwrap();
+        assert!(consecutive > scattered, "{consecutive} <= {scattered}");
+    }
+
+    #[test]
+    fn prefers_matches_at_word_starts() {
+        let word_starts = fuzzy_score("gg", "gonna give").unwrap();
+        let mid_word = fuzzy_score("gg", "xgonna xgive").unwrap();
+        assert!(word_starts > mid_word, "{word_starts} <= {mid_word}");
+        assert!(fuzzy_score("give", "Give Up").unwrap() > fuzzy_score("give", "Forgive").unwrap());
+    }
+}
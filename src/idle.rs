@@ -0,0 +1,208 @@
+use std::{
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use log::{debug, error};
+use poise::{
+    async_trait,
+    serenity_prelude::{
+        Cache, ChannelId, Context as SerenityContext, GuildId, Http, UserId, VoiceState,
+    },
+};
+use songbird::{Event, EventContext, EventHandler, Songbird};
+
+use crate::{controls::disable_panel, snapshot::SnapshotStore, state::GuildStore, types::Data};
+
+/// How often calls are checked for an idle queue.
+pub(crate) const IDLE_CHECK_PERIOD: Duration = Duration::from_secs(15);
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long the queue may stay empty before leaving, configured with `MUSE_IDLE_TIMEOUT` in seconds.
+pub(crate) fn idle_timeout() -> Duration {
+    env::var("MUSE_IDLE_TIMEOUT")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .map_or(DEFAULT_IDLE_TIMEOUT, Duration::from_secs)
+}
+
+/// Leave `guild_id`'s call, if any, and explain why in its announcement channel.
+pub(crate) async fn leave(
+    manager: &Songbird,
+    http: &Http,
+    guilds: &GuildStore,
+    snapshots: &SnapshotStore,
+    guild_id: GuildId,
+    reason: &str,
+) {
+    if manager.get(guild_id).is_none() {
+        return;
+    }
+    snapshots.set(guild_id, None);
+    disable_panel(http, guilds, guild_id).await;
+    guilds.with(guild_id, |g| g.prefetched = None);
+
+    if let Err(e) = manager.remove(guild_id).await {
+        error!("Error while leaving {guild_id}: {e}");
+        return;
+    }
+    debug!("Left {guild_id}: {reason}");
+
+    let Some(channel) = guilds.with(guild_id, |g| g.announce_channel) else {
+        return;
+    };
+    if let Err(e) = channel.say(http, reason).await {
+        error!("Error sending leave notification in {channel}: {e}");
+    }
+}
+
+/// The voice channel `user_id` is in, if any.
+pub(crate) fn user_channel(cache: &Cache, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+    cache
+        .guild_field(guild_id, |guild| {
+            guild
+                .voice_states
+                .get(&user_id)
+                .and_then(|state| state.channel_id)
+        })
+        .flatten()
+}
+
+/// Count the users other than bots in `channel`.
+pub(crate) fn count_listeners(cache: &Cache, guild_id: GuildId, channel: ChannelId) -> usize {
+    let current_user_id = cache.current_user_id();
+    cache
+        .guild_field(guild_id, |guild| {
+            guild
+                .voice_states
+                .values()
+                .filter(|state| state.channel_id == Some(channel))
+                .filter(|state| state.user_id != current_user_id)
+                .filter(|state| {
+                    let is_bot = state
+                        .member
+                        .as_ref()
+                        .map(|member| member.user.bot)
+                        .or_else(|| guild.members.get(&state.user_id).map(|m| m.user.bot))
+                        .or_else(|| cache.user(state.user_id).map(|user| user.bot));
+                    !is_bot.unwrap_or(false)
+                })
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+/// Leave the call once everyone else has left its channel.
+///
+/// Only someone leaving counts, so joining a channel nobody's in yet doesn't leave it straight away.
+pub(crate) async fn on_voice_state_update(
+    ctx: &SerenityContext,
+    data: &Data,
+    old: Option<&VoiceState>,
+    state: &VoiceState,
+) {
+    let Some(guild_id) = state.guild_id else {
+        return;
+    };
+    let Some(left) = old.and_then(|old| old.channel_id) else {
+        return;
+    };
+    if state.channel_id == Some(left) {
+        return;
+    }
+    let Some(manager) = songbird::get(ctx).await else {
+        return;
+    };
+    let Some(handler_lock) = manager.get(guild_id) else {
+        return;
+    };
+
+    let Some(channel) = handler_lock.lock().await.current_channel() else {
+        return;
+    };
+
+    let channel = ChannelId(channel.0);
+    if left == channel && count_listeners(&ctx.cache, guild_id, channel) == 0 {
+        leave(
+            &manager,
+            &ctx.http,
+            &data.guilds,
+            &data.snapshots,
+            guild_id,
+            "Left the voice channel since everyone else left.",
+        )
+        .await;
+    }
+}
+
+/// Leaves the call once the queue has been empty for the idle timeout.
+pub(crate) struct IdleTimeout {
+    manager: Arc<Songbird>,
+    http: Arc<Http>,
+    guilds: GuildStore,
+    snapshots: SnapshotStore,
+    guild_id: GuildId,
+    timeout: Duration,
+    idle_since: Mutex<Option<Instant>>,
+}
+
+impl IdleTimeout {
+    pub(crate) fn new(
+        manager: Arc<Songbird>,
+        http: Arc<Http>,
+        guilds: GuildStore,
+        snapshots: SnapshotStore,
+        guild_id: GuildId,
+    ) -> Self {
+        Self {
+            manager,
+            http,
+            guilds,
+            snapshots,
+            guild_id,
+            timeout: idle_timeout(),
+            idle_since: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for IdleTimeout {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let handler_lock = self.manager.get(self.guild_id)?;
+        let is_empty = handler_lock.lock().await.queue().is_empty();
+
+        let timed_out = {
+            let mut idle_since = self.idle_since.lock().unwrap();
+            if is_empty {
+                idle_since.get_or_insert_with(Instant::now).elapsed() >= self.timeout
+            } else {
+                *idle_since = None;
+                false
+            }
+        };
+
+        if timed_out {
+            let manager = self.manager.clone();
+            let http = self.http.clone();
+            let guilds = self.guilds.clone();
+            let snapshots = self.snapshots.clone();
+            let guild_id = self.guild_id;
+            // Leaving drops this call's driver, so don't do it from inside its event handler.
+            tokio::spawn(async move {
+                leave(
+                    &manager,
+                    &http,
+                    &guilds,
+                    &snapshots,
+                    guild_id,
+                    "Left the voice channel since the queue is empty.",
+                )
+                .await;
+            });
+        }
+
+        None
+    }
+}
@@ -1,20 +1,53 @@
+pub(crate) mod cache;
 pub(crate) mod commands;
+pub(crate) mod controls;
 pub(crate) mod event;
+pub(crate) mod filters;
 pub(crate) mod format;
+pub(crate) mod fuzzy;
+pub(crate) mod idle;
+pub(crate) mod local;
 pub(crate) mod logger;
+pub(crate) mod loudness;
+pub(crate) mod permissions;
+pub(crate) mod playlists;
+pub(crate) mod prefetch;
+pub(crate) mod settings;
+pub(crate) mod shutdown;
+pub(crate) mod snapshot;
+pub(crate) mod state;
+pub(crate) mod store;
+pub(crate) mod track;
 pub(crate) mod types;
+pub(crate) mod ytdl;
 
-use std::env;
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::Result;
 use log::{error, info, trace};
-use poise::{serenity_prelude::GatewayIntents, Framework, FrameworkOptions};
-use songbird::SerenityInit;
+use poise::{
+    serenity_prelude::{Context as SerenityContext, GatewayIntents, Interaction},
+    Event, Framework, FrameworkOptions,
+};
+use shutdown::{shutdown, wait_for_signal};
+use songbird::{SerenityInit, Songbird};
 
+use cache::AudioCache;
 use commands::*;
 use format::format_user_for_log;
 use logger::{log_command, setup_logger};
-use types::{Data, FrameworkError};
+use loudness::LoudnessStore;
+use playlists::PlaylistStore;
+use settings::SettingsStore;
+use snapshot::{restore_queues, SnapshotStore};
+use state::GuildStore;
+use types::{Context, Data, FrameworkError};
 
 async fn on_error(err: FrameworkError<'_>) {
     match err {
@@ -24,6 +57,8 @@ async fn on_error(err: FrameworkError<'_>) {
             format_user_for_log(ctx.author())
         ),
         FrameworkError::UnknownCommand { .. } => return,
+        // Refused while shutting down, and already explained to the user.
+        FrameworkError::CommandCheckFailed { error: None, .. } => return,
         _ => error!("{err}"),
     }
 
@@ -36,37 +71,108 @@ async fn on_error(err: FrameworkError<'_>) {
     }
 }
 
+async fn on_event(ctx: &SerenityContext, event: &Event<'_>, data: &Data) -> Result<()> {
+    match event {
+        Event::VoiceStateUpdate { old, new } => {
+            idle::on_voice_state_update(ctx, data, old.as_ref(), new).await
+        }
+        Event::InteractionCreate {
+            interaction: Interaction::MessageComponent(interaction),
+        } => controls::on_component_interaction(ctx, data, interaction).await?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+async fn check_shutting_down(ctx: Context<'_>) -> Result<bool> {
+    if !ctx.data().shutting_down.load(Ordering::SeqCst) {
+        return Ok(true);
+    }
+
+    ctx.send(|m| {
+        m.content("I'm restarting, try again in a moment.")
+            .ephemeral(true)
+    })
+    .await?;
+    Ok(false)
+}
+
 pub async fn start() -> Result<()> {
     setup_logger()?;
+    let data = Data {
+        guilds: GuildStore::default(),
+        settings: SettingsStore::load()?,
+        snapshots: SnapshotStore::load()?,
+        playlists: PlaylistStore::load()?,
+        loudness: LoudnessStore::load()?,
+        cache: AudioCache::load()?,
+        shutting_down: Arc::new(AtomicBool::new(false)),
+    };
+    let shutdown_data = data.clone();
+    let manager = Songbird::serenity();
     info!("Initializing framework...");
 
     let framework = Framework::builder()
         .options(FrameworkOptions {
             commands: vec![
+                back(),
+                cache(),
+                filter(),
+                history(),
                 leave(),
+                loop_mode(),
+                move_song(),
                 now_playing(),
+                pause(),
                 play(),
+                playlist(),
                 queue(),
                 register(),
                 remove(),
+                resume(),
+                search(),
+                seek(),
+                settings(),
+                shuffle(),
                 skip(),
+                swap(),
+                volume(),
             ],
+            command_check: Some(|ctx| Box::pin(async move { check_shutting_down(ctx).await })),
             pre_command: |ctx| Box::pin(async move { log_command(ctx) }),
             on_error: |err| Box::pin(async move { on_error(err).await }),
+            event_handler: |ctx, event, _framework, data| {
+                Box::pin(async move { on_event(ctx, event, data).await })
+            },
             ..Default::default()
         })
         .token(env::var("DISCORD_TOKEN")?)
         .intents(GatewayIntents::non_privileged())
-        .setup(|_ctx, _ready, _framework| {
+        .setup(|ctx, _ready, _framework| {
             Box::pin(async move {
                 trace!("Setting up framework data...");
-                Ok(Data)
+                let ctx = ctx.clone();
+                let restore_data = data.clone();
+                tokio::spawn(async move { restore_queues(&ctx, &restore_data).await });
+                Ok(data)
             })
         })
-        .client_settings(SerenityInit::register_songbird);
+        .client_settings({
+            let manager = manager.clone();
+            |client| client.register_songbird_with(manager)
+        })
+        .build()
+        .await?;
+
+    let http = framework.client().cache_and_http.http.clone();
+    let shard_manager = framework.shard_manager().clone();
 
     info!("Framework initialized. Starting.");
-    framework.run().await?;
+    tokio::select! {
+        res = framework.start() => res?,
+        _ = wait_for_signal() => shutdown(manager, http, shard_manager, shutdown_data).await,
+    }
 
     Ok(())
 }
@@ -0,0 +1,286 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    path::{Component, Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use log::warn;
+use poise::{async_trait, serenity_prelude::Attachment};
+use songbird::input::{
+    children_to_reader,
+    error::{Error as InputError, Result as InputResult},
+    restartable::Restart,
+    Codec, Container, Input, Metadata, Restartable,
+};
+use tokio::process::Command as TokioCommand;
+
+use crate::{
+    filters::GuildFilters,
+    types::*,
+    ytdl::{from_beginning, spawn_ffmpeg, ytdl_input, LazyYtdl},
+};
+
+/// Maximum number of files listed when suggesting local songs.
+const MAX_LISTED_FILES: usize = 1000;
+/// How local songs' URLs start, so they can be queued again like any other song.
+const FILE_URL_PREFIX: &str = "file://";
+
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "aiff", "alac", "flac", "m4a", "mka", "mp3", "ogg", "opus", "wav", "webm", "wma",
+];
+
+/// The directory local songs may be played from, configured with `MUSE_MUSIC_DIR`.
+pub(crate) fn music_dir() -> Option<PathBuf> {
+    let dir = env::var_os("MUSE_MUSIC_DIR")?;
+    match fs::canonicalize(&dir) {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            warn!("Invalid MUSE_MUSIC_DIR `{}`: {e}", dir.to_string_lossy());
+            None
+        }
+    }
+}
+
+/// Resolve `requested` to a file inside `root`, refusing anything that escapes it.
+pub(crate) fn resolve_in(root: &Path, requested: &str) -> Option<PathBuf> {
+    let requested = Path::new(requested);
+    if !requested
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+
+    // Canonicalizing resolves symlinks, which could otherwise point outside the root.
+    let path = fs::canonicalize(root.join(requested)).ok()?;
+    (path.starts_with(root) && path.is_file()).then_some(path)
+}
+
+pub(crate) fn is_file_url(url: &str) -> bool {
+    url.starts_with(FILE_URL_PREFIX)
+}
+
+fn file_url(path: &Path) -> String {
+    format!("{FILE_URL_PREFIX}{}", path.to_string_lossy())
+}
+
+/// The file in the music library at `root` that `url` points to, if it's still there.
+fn file_path(root: &Path, url: &str) -> Option<PathBuf> {
+    let path = fs::canonicalize(url.strip_prefix(FILE_URL_PREFIX)?).ok()?;
+    (path.starts_with(root) && path.is_file()).then_some(path)
+}
+
+/// List the first audio files in `root` by name, as paths relative to it.
+pub(crate) fn list_files(root: &Path) -> Vec<String> {
+    let mut files = vec![];
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if is_audio_file(&path) {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    files.push(relative.to_string_lossy().into_owned());
+                }
+            }
+        }
+    }
+
+    files.sort();
+    files.truncate(MAX_LISTED_FILES);
+    files
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+pub(crate) fn is_audio_attachment(attachment: &Attachment) -> bool {
+    match &attachment.content_type {
+        Some(content_type) => {
+            content_type.starts_with("audio/") || content_type.starts_with("video/")
+        }
+        None => is_audio_file(Path::new(&attachment.filename)),
+    }
+}
+
+/// A file or URL decoded by ffmpeg, picking up its guild's filters whenever it starts or restarts.
+struct FfmpegSource {
+    path: OsString,
+    /// The song's URL, which its loudness and restarts are kept under.
+    url: String,
+    filters: GuildFilters,
+}
+
+#[async_trait]
+impl Restart for FfmpegSource {
+    async fn call_restart(&mut self, time: Option<Duration>) -> InputResult<Input> {
+        let url = &self.url;
+        let time = self.filters.restart_time(url, time);
+        if from_beginning(time) {
+            if let Some(input) = self.filters.take_prefetched(url) {
+                return Ok(input);
+            }
+        }
+        let chain = self.filters.chain(url, time);
+        let mut ffmpeg = spawn_ffmpeg(&self.path, Stdio::null(), time, &chain)?;
+        self.filters.record_loudness(url, &chain, &mut ffmpeg);
+        Ok(Input::new(
+            true,
+            children_to_reader::<f32>(vec![ffmpeg]),
+            Codec::FloatPcm,
+            Container::Raw,
+            None,
+        ))
+    }
+
+    async fn lazy_init(&mut self) -> InputResult<(Option<Metadata>, Codec, Container)> {
+        let output = TokioCommand::new("ffprobe")
+            .args([
+                "-v",
+                "quiet",
+                "-of",
+                "json",
+                "-show_format",
+                "-show_streams",
+                "-i",
+            ])
+            .arg(&self.path)
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+        let value = serde_json::from_slice(&output.stdout).map_err(|error| InputError::Json {
+            error,
+            parsed_text: String::from_utf8_lossy(&output.stdout).into_owned(),
+        })?;
+        Ok((
+            Some(Metadata::from_ffprobe_json(&value)),
+            Codec::FloatPcm,
+            Container::Raw,
+        ))
+    }
+}
+
+async fn ffmpeg_input(
+    path: impl Into<OsString>,
+    url: String,
+    filters: GuildFilters,
+) -> Result<Input> {
+    let source = FfmpegSource {
+        path: path.into(),
+        url: url.clone(),
+        filters,
+    };
+    let mut input: Input = Restartable::new(source, true).await?.into();
+    input.metadata.source_url = Some(url);
+    Ok(input)
+}
+
+/// Create a source for a local file, reading its metadata from its tags.
+pub(crate) async fn file_input(path: &Path, filters: GuildFilters) -> Result<Input> {
+    let fallback_title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned());
+
+    let mut input = ffmpeg_input(path, file_url(path), filters).await?;
+    input.metadata.title = input.metadata.track.clone().or(fallback_title);
+    Ok(input)
+}
+
+/// Create a source for a Discord attachment, reading its metadata from its tags.
+pub(crate) async fn attachment_input(
+    attachment: &Attachment,
+    filters: GuildFilters,
+) -> Result<Input> {
+    let fallback_title = Path::new(&attachment.filename)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned());
+
+    let mut input = ffmpeg_input(&attachment.url, attachment.url.clone(), filters).await?;
+    input.metadata.title = input.metadata.track.clone().or(fallback_title);
+    Ok(input)
+}
+
+/// Create a source for a song queued before, whether it's in the music library or from yt-dlp.
+pub(crate) async fn song_input(url: String, filters: GuildFilters) -> Result<Input> {
+    if !is_file_url(&url) {
+        return Ok(ytdl_input(url, filters).await?);
+    }
+    let path = music_dir()
+        .and_then(|root| file_path(&root, &url))
+        .ok_or_else(|| anyhow!("`{url}` isn't in the music library."))?;
+    file_input(&path, filters).await
+}
+
+/// Like [`song_input`], but leaving yt-dlp songs to be looked up once they come up.
+pub(crate) async fn lazy_song_input(metadata: Metadata, filters: GuildFilters) -> Result<Input> {
+    match &metadata.source_url {
+        Some(url) if is_file_url(url) => song_input(url.clone(), filters).await,
+        _ => Ok(Restartable::new(LazyYtdl::new(metadata, filters), true)
+            .await?
+            .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A music library in a fresh directory, with `files` in it.
+    fn library(name: &str, files: &[&str]) -> PathBuf {
+        let root = env::temp_dir().join(format!("muse-library-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        fs::canonicalize(root).unwrap()
+    }
+
+    #[test]
+    fn lists_audio_files_by_name() {
+        let root = library(
+            "list",
+            &["b.mp3", "a/z.flac", "a/cover.jpg", "C.OGG", "notes.txt"],
+        );
+        let expected = ["C.OGG", "a/z.flac", "b.mp3"]
+            .map(|file| Path::new(file).to_string_lossy().into_owned());
+        assert_eq!(list_files(&root), expected);
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn file_urls_stay_in_the_library() {
+        let root = library("urls", &["music/song.mp3", "secret.mp3"]);
+        let music = root.join("music");
+        let song = music.join("song.mp3");
+
+        let url = file_url(&song);
+        assert!(is_file_url(&url));
+        assert_eq!(file_path(&music, &url), Some(song));
+
+        let escape = format!("{FILE_URL_PREFIX}{}/../secret.mp3", music.display());
+        assert_eq!(file_path(&music, &escape), None);
+        assert_eq!(file_path(&music, &file_url(&root.join("secret.mp3"))), None);
+        assert_eq!(file_path(&music, &file_url(&music.join("gone.mp3"))), None);
+        assert_eq!(file_path(&music, "https://example.com/song.mp3"), None);
+        let _ = fs::remove_dir_all(root);
+    }
+}
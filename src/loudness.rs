@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::Read,
+    path::PathBuf,
+    process::ChildStderr,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::Result;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::store::{load_json, save_json};
+
+const DEFAULT_LOUDNESS_PATH: &str = "loudness.json";
+/// Integrated loudness songs are normalized to, in LUFS.
+const TARGET_LOUDNESS: f64 = -16.0;
+/// Highest true peak a normalized song may reach, in dBTP.
+const TARGET_TRUE_PEAK: f64 = -1.5;
+/// Loudness range allowed while measuring a song, in LU.
+const TARGET_RANGE: f64 = 11.0;
+
+/// A song's loudness, as measured by ffmpeg's `loudnorm` filter.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct Loudness {
+    /// Integrated loudness in LUFS.
+    pub(crate) integrated: f64,
+    /// True peak in dBTP.
+    pub(crate) true_peak: f64,
+}
+
+impl Loudness {
+    /// Gain in dB that brings the song to the target loudness without its peaks clipping.
+    pub(crate) fn gain(&self) -> f64 {
+        (TARGET_LOUDNESS - self.integrated).min(TARGET_TRUE_PEAK - self.true_peak)
+    }
+
+    /// Read the measurement `loudnorm` prints as JSON once it reaches the end of a song.
+    fn parse(output: &str) -> Option<Self> {
+        let start = output.rfind('{')?;
+        let end = start + output[start..].find('}')?;
+        let value: Value = serde_json::from_str(&output[start..=end]).ok()?;
+        let field = |name| value.get(name)?.as_str()?.trim().parse::<f64>().ok();
+
+        let loudness = Self {
+            integrated: field("input_i")?,
+            true_peak: field("input_tp")?,
+        };
+        // Silence measures as -inf or -70 LUFS, which would ask for a huge gain.
+        (loudness.integrated.is_finite() && loudness.integrated > -70.0).then_some(loudness)
+    }
+}
+
+/// The ffmpeg filter that normalizes a song measured as `loudness`, or measures it if it hasn't been.
+pub(crate) fn normalize_filter(loudness: Option<Loudness>) -> String {
+    match loudness {
+        Some(loudness) => format!("volume={:.2}dB", loudness.gain()),
+        None => format!(
+            "loudnorm=I={TARGET_LOUDNESS}:TP={TARGET_TRUE_PEAK}:LRA={TARGET_RANGE}:print_format=json"
+        ),
+    }
+}
+
+/// Shared cache of measured [`Loudness`] by song URL, saved to a JSON file whenever it changes.
+#[derive(Clone, Debug)]
+pub(crate) struct LoudnessStore {
+    path: PathBuf,
+    songs: Arc<Mutex<HashMap<String, Loudness>>>,
+}
+
+impl LoudnessStore {
+    /// Load the loudness file named by `MUSE_LOUDNESS_PATH`, starting empty if it doesn't exist.
+    pub(crate) fn load() -> Result<Self> {
+        let path = env::var("MUSE_LOUDNESS_PATH")
+            .map_or_else(|_| PathBuf::from(DEFAULT_LOUDNESS_PATH), PathBuf::from);
+
+        let songs = load_json(&path)?;
+
+        Ok(Self {
+            path,
+            songs: Arc::new(Mutex::new(songs)),
+        })
+    }
+
+    pub(crate) fn get(&self, url: &str) -> Option<Loudness> {
+        self.songs.lock().unwrap().get(url).copied()
+    }
+
+    pub(crate) fn set(&self, url: String, loudness: Loudness) {
+        let mut songs = self.songs.lock().unwrap();
+        songs.insert(url, loudness);
+
+        if let Err(e) = save_json(&self.path, &*songs) {
+            error!("Error while saving loudness measurements: {e}");
+        }
+    }
+
+    /// Remember the measurement ffmpeg prints to `stderr` for `url`, once it finishes the song.
+    pub(crate) fn record(&self, url: String, mut stderr: ChildStderr) {
+        let store = self.clone();
+        thread::spawn(move || {
+            let mut output = String::new();
+            // Songs stopped early end without a measurement, and are measured again next time.
+            if stderr.read_to_string(&mut output).is_err() {
+                return;
+            }
+            if let Some(loudness) = Loudness::parse(&output) {
+                debug!("Measured `{url}` at {} LUFS.", loudness.integrated);
+                store.set(url, loudness);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    /// What `loudnorm` prints for a stereo 997 Hz sine at -20 dBFS, after ffmpeg's usual output.
+    const SINE_OUTPUT: &str = r#"Input #0, wav, from 'sine-997hz-20lufs.wav':
+  Duration: 00:00:04.00, bitrate: 705 kb/s
+  Stream #0:0: Audio: pcm_s16le ([1][0][0][0] / 0x0001), 22050 Hz, 2 channels, s16, 705 kb/s
+Stream mapping:
+  Stream #0:0 -> #0:0 (pcm_s16le (native) -> pcm_s16le (native))
+Output #0, null, to 'pipe:':
+  Stream #0:0: Audio: pcm_s16le, 192000 Hz, stereo, s16, 6144 kb/s
+size=N/A time=00:00:04.00 bitrate=N/A speed= 105x
+[Parsed_loudnorm_0 @ 0x55d0c2a3e0c0] 
+{
+	"input_i" : "-20.01",
+	"input_tp" : "-19.98",
+	"input_lra" : "0.00",
+	"input_thresh" : "-30.01",
+	"output_i" : "-16.02",
+	"output_tp" : "-15.99",
+	"output_lra" : "0.00",
+	"output_thresh" : "-26.02",
+	"normalization_type" : "linear",
+	"target_offset" : "0.02"
+}
+"#;
+
+    /// What `loudnorm` prints for silence.
+    const SILENCE_OUTPUT: &str = r#"size=N/A time=00:00:04.00 bitrate=N/A speed= 410x
+[Parsed_loudnorm_0 @ 0x5612e8f4a0c0] 
+{
+	"input_i" : "-inf",
+	"input_tp" : "-inf",
+	"input_lra" : "0.00",
+	"input_thresh" : "-70.00",
+	"output_i" : "-inf",
+	"output_tp" : "-inf",
+	"output_lra" : "0.00",
+	"output_thresh" : "-70.00",
+	"normalization_type" : "dynamic",
+	"target_offset" : "inf"
+}
+"#;
+
+    /// Measure a file in `tests/fixtures` with ffmpeg.
+    fn measure(fixture: &str) -> Option<Loudness> {
+        let path = format!("{}/tests/fixtures/{fixture}", env!("CARGO_MANIFEST_DIR"));
+        let output = Command::new("ffmpeg")
+            .args(["-hide_banner", "-nostats", "-i", &path, "-af"])
+            .arg(normalize_filter(None))
+            .args(["-f", "null", "-"])
+            .output()
+            .expect("ffmpeg should be installed");
+        assert!(output.status.success());
+        Loudness::parse(&String::from_utf8_lossy(&output.stderr))
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg, run with `cargo test -- --ignored`"]
+    fn measures_fixture_at_known_loudness() {
+        let loudness = measure("sine-997hz-20lufs.wav").unwrap();
+        assert!((loudness.integrated + 20.0).abs() < 0.5, "{loudness:?}");
+        assert!((loudness.true_peak + 20.0).abs() < 0.5, "{loudness:?}");
+    }
+
+    #[test]
+    #[ignore = "needs ffmpeg, run with `cargo test -- --ignored`"]
+    fn silent_fixture_isnt_measured() {
+        let loudness = measure("silence.wav");
+        assert!(loudness.is_none(), "{loudness:?}");
+    }
+
+    #[test]
+    fn parses_loudnorm_output() {
+        let loudness = Loudness::parse(SINE_OUTPUT).unwrap();
+        assert_eq!(loudness.integrated, -20.01);
+        assert_eq!(loudness.true_peak, -19.98);
+
+        assert!(Loudness::parse(SILENCE_OUTPUT).is_none());
+        assert!(Loudness::parse("size=N/A time=00:00:01.00 bitrate=N/A").is_none());
+    }
+
+    #[test]
+    fn gain_respects_true_peak() {
+        let quiet = Loudness {
+            integrated: -20.0,
+            true_peak: -10.0,
+        };
+        assert_eq!(quiet.gain(), 4.0);
+        assert_eq!(normalize_filter(Some(quiet)), "volume=4.00dB");
+
+        // Bringing this up 14 dB would clip, so it only goes up to the peak limit.
+        let peaky = Loudness {
+            integrated: -30.0,
+            true_peak: -3.0,
+        };
+        assert_eq!(peaky.gain(), 1.5);
+
+        let loud = Loudness {
+            integrated: -8.0,
+            true_peak: 0.5,
+        };
+        assert_eq!(loud.gain(), -8.0);
+    }
+
+    #[test]
+    fn unmeasured_songs_are_measured() {
+        assert!(normalize_filter(None).starts_with("loudnorm="));
+        assert!(normalize_filter(None).contains("print_format=json"));
+    }
+}
@@ -0,0 +1,43 @@
+use std::env;
+
+use poise::serenity_prelude::{Cache, GuildId, Member, RoleId};
+
+const DEFAULT_DJ_ROLE: &str = "DJ";
+const DEFAULT_SKIP_FRACTION: f64 = 0.5;
+
+/// Whether `member` has the DJ role, which is `dj_role` if the guild set one or else named by
+/// `MUSE_DJ_ROLE`.
+pub(crate) fn is_dj(
+    cache: &Cache,
+    guild_id: GuildId,
+    member: &Member,
+    dj_role: Option<RoleId>,
+) -> bool {
+    if let Some(dj_role) = dj_role {
+        return member.roles.contains(&dj_role);
+    }
+
+    let dj_role = env::var("MUSE_DJ_ROLE").unwrap_or_else(|_| DEFAULT_DJ_ROLE.to_string());
+    cache
+        .guild_field(guild_id, |guild| {
+            member.roles.iter().any(|role_id| {
+                guild
+                    .roles
+                    .get(role_id)
+                    .is_some_and(|role| role.name.eq_ignore_ascii_case(&dj_role))
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Number of votes needed to skip a song with `listeners` in the channel.
+///
+/// This is a fraction of the listeners, configured with `MUSE_SKIP_FRACTION`.
+pub(crate) fn required_skip_votes(listeners: usize) -> usize {
+    let fraction = env::var("MUSE_SKIP_FRACTION")
+        .ok()
+        .and_then(|fraction| fraction.parse::<f64>().ok())
+        .filter(|fraction| (0.0..=1.0).contains(fraction))
+        .unwrap_or(DEFAULT_SKIP_FRACTION);
+    ((listeners as f64 * fraction).ceil() as usize).max(1)
+}
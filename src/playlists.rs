@@ -0,0 +1,179 @@
+use std::{
+    env,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use poise::serenity_prelude::{GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use songbird::input::Metadata;
+
+use crate::{
+    store::{load_json, save_json},
+    ytdl::Playlist,
+};
+
+const DEFAULT_PLAYLISTS_PATH: &str = "playlists.json";
+
+/// Who a saved playlist belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum PlaylistOwner {
+    /// A personal playlist, usable in any guild.
+    User(UserId),
+    /// A playlist shared by everyone in a guild, edited by its DJs.
+    Guild(GuildId),
+}
+
+/// A song in a saved playlist.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct PlaylistSong {
+    pub(crate) title: String,
+    pub(crate) url: String,
+    pub(crate) duration: Option<Duration>,
+}
+
+impl PlaylistSong {
+    pub(crate) fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        Some(Self {
+            title: metadata.title.clone()?,
+            url: metadata.source_url.clone()?,
+            duration: metadata.duration,
+        })
+    }
+
+    pub(crate) fn metadata(&self) -> Metadata {
+        Metadata {
+            title: Some(self.title.clone()),
+            source_url: Some(self.url.clone()),
+            duration: self.duration,
+            ..Default::default()
+        }
+    }
+}
+
+/// A named list of songs saved with `/playlist`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct SavedPlaylist {
+    pub(crate) name: String,
+    pub(crate) owner: PlaylistOwner,
+    pub(crate) songs: Vec<PlaylistSong>,
+}
+
+impl SavedPlaylist {
+    /// The playlist's songs, ready to be queued.
+    pub(crate) fn to_playlist(&self) -> Playlist {
+        Playlist {
+            title: Some(self.name.clone()),
+            url: None,
+            entries: self.songs.iter().map(PlaylistSong::metadata).collect(),
+        }
+    }
+}
+
+/// Shared store of [`SavedPlaylist`]s, saved to a JSON file whenever they change.
+#[derive(Clone, Debug)]
+pub(crate) struct PlaylistStore {
+    path: PathBuf,
+    playlists: Arc<Mutex<Vec<SavedPlaylist>>>,
+}
+
+impl PlaylistStore {
+    /// Load the playlist file named by `MUSE_PLAYLISTS_PATH`, starting empty if it doesn't exist.
+    pub(crate) fn load() -> Result<Self> {
+        let path = env::var("MUSE_PLAYLISTS_PATH")
+            .map_or_else(|_| PathBuf::from(DEFAULT_PLAYLISTS_PATH), PathBuf::from);
+
+        let playlists = load_json(&path)?;
+
+        Ok(Self {
+            path,
+            playlists: Arc::new(Mutex::new(playlists)),
+        })
+    }
+
+    /// Playlists `user` can use in `guild_id`: their own, then the guild's.
+    pub(crate) fn visible(&self, user: UserId, guild_id: GuildId) -> Vec<SavedPlaylist> {
+        let playlists = self.playlists.lock().unwrap();
+        let owned_by = |owner| playlists.iter().filter(move |p| p.owner == owner);
+        owned_by(PlaylistOwner::User(user))
+            .chain(owned_by(PlaylistOwner::Guild(guild_id)))
+            .cloned()
+            .collect()
+    }
+
+    /// The playlist called `name` that `user` can use in `guild_id`, preferring their own.
+    pub(crate) fn find(
+        &self,
+        user: UserId,
+        guild_id: GuildId,
+        name: &str,
+    ) -> Option<SavedPlaylist> {
+        self.visible(user, guild_id)
+            .into_iter()
+            .find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Add a new playlist, returning `false` if its owner already has one with the same name.
+    pub(crate) fn create(&self, playlist: SavedPlaylist) -> Result<bool> {
+        let mut playlists = self.playlists.lock().unwrap();
+        if playlists
+            .iter()
+            .any(|p| p.owner == playlist.owner && p.name.eq_ignore_ascii_case(&playlist.name))
+        {
+            return Ok(false);
+        }
+
+        playlists.push(playlist);
+        save_json(&self.path, &*playlists)?;
+        Ok(true)
+    }
+
+    /// Change `owner`'s playlist called `name` with `f`, returning `None` if there isn't one.
+    pub(crate) fn update<T>(
+        &self,
+        owner: PlaylistOwner,
+        name: &str,
+        f: impl FnOnce(&mut SavedPlaylist) -> T,
+    ) -> Result<Option<T>> {
+        let mut playlists = self.playlists.lock().unwrap();
+        let Some(playlist) = playlists
+            .iter_mut()
+            .find(|p| p.owner == owner && p.name.eq_ignore_ascii_case(name))
+        else {
+            return Ok(None);
+        };
+
+        let result = f(playlist);
+        save_json(&self.path, &*playlists)?;
+        Ok(Some(result))
+    }
+
+    /// Rename `owner`'s playlist called `name`, returning `false` if the new name is taken.
+    pub(crate) fn rename(&self, owner: PlaylistOwner, name: &str, new_name: &str) -> Result<bool> {
+        let mut playlists = self.playlists.lock().unwrap();
+        if playlists.iter().any(|p| {
+            p.owner == owner
+                && p.name.eq_ignore_ascii_case(new_name)
+                && !p.name.eq_ignore_ascii_case(name)
+        }) {
+            return Ok(false);
+        }
+
+        for playlist in playlists.iter_mut() {
+            if playlist.owner == owner && playlist.name.eq_ignore_ascii_case(name) {
+                playlist.name = new_name.to_string();
+            }
+        }
+        save_json(&self.path, &*playlists)?;
+        Ok(true)
+    }
+
+    /// Delete `owner`'s playlist called `name`.
+    pub(crate) fn delete(&self, owner: PlaylistOwner, name: &str) -> Result<()> {
+        let mut playlists = self.playlists.lock().unwrap();
+        playlists.retain(|p| !(p.owner == owner && p.name.eq_ignore_ascii_case(name)));
+        save_json(&self.path, &*playlists)
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    env,
+    io::{self, BufRead},
+    sync::Weak,
+    time::Duration,
+};
+
+use log::{debug, error};
+use poise::{async_trait, serenity_prelude::GuildId};
+use songbird::{
+    input::{Input, Reader},
+    tracks::{PlayMode, TrackHandle},
+    Call, Event, EventContext, EventHandler,
+};
+use tokio::{sync::Mutex, task};
+use uuid::Uuid;
+
+use crate::{
+    filters::GuildFilters,
+    local::is_file_url,
+    settings::SettingsStore,
+    state::{GuildStore, LoopMode},
+    track::seek_anchor,
+    types::Data,
+    ytdl::ytdl_from,
+};
+
+/// How often the current song is checked for being close to its end.
+pub(crate) const PREFETCH_CHECK_PERIOD: Duration = Duration::from_millis(500);
+const DEFAULT_PREFETCH_LEAD: Duration = Duration::from_secs(15);
+
+/// The next song's input, started before the current song ends.
+#[derive(Debug)]
+pub(crate) struct Prefetched {
+    pub(crate) track: Uuid,
+    pub(crate) url: String,
+    /// The ffmpeg filters the input was started with.
+    pub(crate) filters: Option<String>,
+    /// `None` until the input has produced audio, and for good if it failed to.
+    pub(crate) input: Option<Input>,
+}
+
+/// Starts the next song shortly before the current one ends, fading one out and the other in.
+pub(crate) struct Prefetcher {
+    call: Weak<Mutex<Call>>,
+    guild_id: GuildId,
+    guilds: GuildStore,
+    settings: SettingsStore,
+    filters: GuildFilters,
+    /// How long before a song ends to start the next one, configured with `MUSE_PREFETCH_SECONDS`.
+    lead: Duration,
+}
+
+impl Prefetcher {
+    pub(crate) fn new(call: Weak<Mutex<Call>>, guild_id: GuildId, data: &Data) -> Self {
+        Self {
+            call,
+            guild_id,
+            guilds: data.guilds.clone(),
+            settings: data.settings.clone(),
+            filters: GuildFilters::new(data, guild_id),
+            lead: env::var("MUSE_PREFETCH_SECONDS")
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map_or(DEFAULT_PREFETCH_LEAD, Duration::from_secs),
+        }
+    }
+
+    /// Start `next` and wait for its first audio, leaving it for its source to pick up.
+    async fn prefetch(&self, next: &TrackHandle) {
+        // Local files start quickly enough anyway.
+        let Some(url) = next
+            .metadata()
+            .source_url
+            .clone()
+            .filter(|url| !is_file_url(url))
+        else {
+            return;
+        };
+        let already = self.guilds.with(self.guild_id, |g| {
+            g.prefetched.as_ref().map(|prefetched| prefetched.track)
+        });
+        if already == Some(next.uuid()) {
+            return;
+        }
+
+        let filters = self.filters.chain(&url, None).filters;
+        self.guilds.with(self.guild_id, |g| {
+            g.prefetched = Some(Prefetched {
+                track: next.uuid(),
+                url: url.clone(),
+                filters,
+                input: None,
+            });
+        });
+
+        let input = match ytdl_from(&url, None, &self.filters).await {
+            Ok(input) => input,
+            Err(e) => {
+                error!("Error while prefetching `{url}`: {e}");
+                return;
+            }
+        };
+
+        // Waiting for the audio could hold up the call's other events for a while.
+        let guilds = self.guilds.clone();
+        let guild_id = self.guild_id;
+        let track = next.uuid();
+        task::spawn_blocking(move || match wait_for_audio(input) {
+            Ok(input) => {
+                debug!("Prefetched `{url}` in {guild_id}.");
+                guilds.with(guild_id, |g| {
+                    if let Some(prefetched) = &mut g.prefetched {
+                        if prefetched.track == track {
+                            prefetched.input = Some(input);
+                        }
+                    }
+                });
+            }
+            // The track starts from scratch when it comes up instead.
+            Err(e) => error!("Error while prefetching `{url}`: {e}"),
+        });
+    }
+
+    /// Fade `np` out, `remaining` before it ends, and have `next` fade in once it starts.
+    ///
+    /// The queue still starts `next` itself, so it's muted until then rather than played over `np`.
+    fn fade_out(&self, np: &TrackHandle, next: &TrackHandle, remaining: Duration, fade: Duration) {
+        let volume = self.guilds.with(self.guild_id, |g| {
+            g.fading = Some(next.uuid());
+            g.volume
+        });
+        let _ = np.set_volume(volume * fade_progress(remaining, fade));
+        let _ = next.set_volume(0.0);
+    }
+}
+
+/// How far through fading in a song is, `elapsed` into a `fade`, from `0.0` to `1.0`.
+fn fade_progress(elapsed: Duration, fade: Duration) -> f32 {
+    if fade.is_zero() {
+        return 1.0;
+    }
+    (elapsed.as_secs_f32() / fade.as_secs_f32()).min(1.0)
+}
+
+#[async_trait]
+impl EventHandler for Prefetcher {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let call = self.call.upgrade()?;
+        let queue = call.lock().await.queue().clone();
+        let tracks = queue.current_queue();
+        let np = tracks.first()?;
+        let next = tracks.get(1);
+
+        let (volume, loop_mode, fading, speed) = self.guilds.with(self.guild_id, |g| {
+            (g.volume, g.loop_mode, g.fading, g.filters.speed_factor())
+        });
+        let fade = self.settings.get(self.guild_id).fade();
+        let info = np.get_info().await.ok()?;
+
+        if let Some(fading) = fading {
+            if fading == np.uuid() {
+                // The song that was muted is the current one now, so bring it up as it starts.
+                let progress = fade_progress(info.position, fade.unwrap_or_default());
+                let _ = np.set_volume(volume * progress);
+                if progress >= 1.0 {
+                    self.guilds.with(self.guild_id, |g| g.fading = None);
+                }
+            } else if next.map(|next| next.uuid()) != Some(fading) {
+                // It was moved out of the way, so it plays at full volume whenever it comes up.
+                if let Some(track) = tracks.iter().find(|track| track.uuid() == fading) {
+                    let _ = track.set_volume(volume);
+                }
+                self.guilds.with(self.guild_id, |g| g.fading = None);
+            }
+        }
+
+        let next = next?;
+        if info.playing != PlayMode::Play || loop_mode == LoopMode::Track {
+            return None;
+        }
+
+        // Faster songs end sooner than their metadata says.
+        let anchor = seek_anchor(np, speed);
+        let remaining = np
+            .metadata()
+            .duration?
+            .saturating_sub(anchor.song_position(info.position))
+            .div_f64(anchor.speed);
+
+        if remaining <= self.lead.max(fade.unwrap_or_default()) {
+            self.prefetch(next).await;
+        }
+        match fade.filter(|fade| remaining <= *fade) {
+            Some(fade) => self.fade_out(np, next, remaining, fade),
+            // Seeking back out of the fade turns both songs back up.
+            None if fading == Some(next.uuid()) => {
+                let _ = np.set_volume(volume);
+                let _ = next.set_volume(volume);
+                self.guilds.with(self.guild_id, |g| g.fading = None);
+            }
+            None => {}
+        }
+
+        None
+    }
+}
+
+/// Block until `input` produces audio, so a song that fails to start is noticed early.
+fn wait_for_audio(mut input: Input) -> io::Result<Input> {
+    if let Reader::Pipe(pipe) = &mut input.reader {
+        if pipe.fill_buf()?.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "ended without any audio",
+            ));
+        }
+    }
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_over_the_whole_fade() {
+        let fade = Duration::from_secs(4);
+        assert_eq!(fade_progress(Duration::ZERO, fade), 0.0);
+        assert_eq!(fade_progress(Duration::from_secs(1), fade), 0.25);
+        assert_eq!(fade_progress(Duration::from_secs(4), fade), 1.0);
+        assert_eq!(fade_progress(Duration::from_secs(9), fade), 1.0);
+        assert_eq!(fade_progress(Duration::ZERO, Duration::ZERO), 1.0);
+    }
+}
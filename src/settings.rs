@@ -0,0 +1,186 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use poise::{
+    serenity_prelude::{ChannelId, GuildId, RoleId},
+    ChoiceParameter,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Mutex as AsyncMutex, task};
+
+use crate::store::{load_guild_map, save_guild_map};
+
+const DEFAULT_SETTINGS_PATH: &str = "settings.json";
+pub(crate) const DEFAULT_EMBED_COLOUR: u32 = 0x0789f0;
+pub(crate) const DEFAULT_PAGE_SIZE: usize = 5;
+pub(crate) const MAX_PAGE_SIZE: usize = 10;
+pub(crate) const MAX_FADE: u64 = 10;
+
+/// Per-guild settings, changed with `/settings`. Unset settings use their defaults.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct GuildSettings {
+    /// Channel for playback notifications, instead of wherever the bot was summoned from.
+    pub(crate) announce_channel: Option<ChannelId>,
+    /// Role that can skip and remove anyone's songs, instead of the role named `MUSE_DJ_ROLE`.
+    pub(crate) dj_role: Option<RoleId>,
+    /// Volume percentage when joining a voice channel.
+    pub(crate) default_volume: Option<u16>,
+    pub(crate) max_queue_length: Option<usize>,
+    /// Longest song that can be queued, in seconds.
+    pub(crate) max_track_duration: Option<u64>,
+    pub(crate) embed_colour: Option<u32>,
+    /// Songs per `/queue` page.
+    pub(crate) page_size: Option<usize>,
+    /// Whether to rejoin and restore the queue after a restart.
+    pub(crate) restore_queue: Option<bool>,
+    /// Whether to even out the loudness of songs.
+    pub(crate) normalize_loudness: Option<bool>,
+    /// Seconds to fade each song out and the next one in over.
+    #[serde(alias = "crossfade")]
+    pub(crate) fade: Option<u64>,
+}
+
+impl GuildSettings {
+    pub(crate) fn default_volume(&self) -> f32 {
+        f32::from(self.default_volume.unwrap_or(100)) / 100.0
+    }
+
+    pub(crate) fn max_track_duration(&self) -> Option<Duration> {
+        self.max_track_duration.map(Duration::from_secs)
+    }
+
+    pub(crate) fn embed_colour(&self) -> u32 {
+        self.embed_colour.unwrap_or(DEFAULT_EMBED_COLOUR)
+    }
+
+    pub(crate) fn page_size(&self) -> usize {
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+
+    pub(crate) fn restore_queue(&self) -> bool {
+        self.restore_queue.unwrap_or(false)
+    }
+
+    pub(crate) fn normalize_loudness(&self) -> bool {
+        self.normalize_loudness.unwrap_or(false)
+    }
+
+    pub(crate) fn fade(&self) -> Option<Duration> {
+        self.fade.filter(|&secs| secs > 0).map(Duration::from_secs)
+    }
+
+    /// Whether a song of length `duration` may be queued. Songs of unknown length always may.
+    pub(crate) fn allows_duration(&self, duration: Option<&Duration>) -> bool {
+        match (self.max_track_duration(), duration) {
+            (Some(max), Some(duration)) => *duration <= max,
+            _ => true,
+        }
+    }
+
+    /// Number of songs that can be added to a queue of `len` songs.
+    pub(crate) fn queue_space(&self, len: usize) -> usize {
+        self.max_queue_length
+            .map_or(usize::MAX, |max| max.saturating_sub(len))
+    }
+
+    pub(crate) fn reset(&mut self, setting: Setting) {
+        match setting {
+            Setting::AnnounceChannel => self.announce_channel = None,
+            Setting::DjRole => self.dj_role = None,
+            Setting::DefaultVolume => self.default_volume = None,
+            Setting::MaxQueueLength => self.max_queue_length = None,
+            Setting::MaxTrackDuration => self.max_track_duration = None,
+            Setting::EmbedColour => self.embed_colour = None,
+            Setting::PageSize => self.page_size = None,
+            Setting::RestoreQueue => self.restore_queue = None,
+            Setting::NormalizeLoudness => self.normalize_loudness = None,
+            Setting::Fade => self.fade = None,
+        }
+    }
+}
+
+/// A single field of [`GuildSettings`].
+#[derive(ChoiceParameter, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Setting {
+    #[name = "announce_channel"]
+    AnnounceChannel,
+    #[name = "dj_role"]
+    DjRole,
+    #[name = "default_volume"]
+    DefaultVolume,
+    #[name = "max_queue_length"]
+    MaxQueueLength,
+    #[name = "max_song_length"]
+    MaxTrackDuration,
+    #[name = "embed_colour"]
+    EmbedColour,
+    #[name = "page_size"]
+    PageSize,
+    #[name = "restore_queue"]
+    RestoreQueue,
+    #[name = "normalize_loudness"]
+    NormalizeLoudness,
+    #[name = "fade"]
+    Fade,
+}
+
+/// Shared store of [`GuildSettings`], saved to a JSON file whenever they change.
+#[derive(Clone, Debug)]
+pub(crate) struct SettingsStore {
+    path: PathBuf,
+    guilds: Arc<Mutex<HashMap<GuildId, GuildSettings>>>,
+    /// Held while the settings are updated, so concurrent updates don't undo each other.
+    saving: Arc<AsyncMutex<()>>,
+}
+
+impl SettingsStore {
+    /// Load the settings file named by `MUSE_SETTINGS_PATH`, starting empty if it doesn't exist.
+    pub(crate) fn load() -> Result<Self> {
+        let path = env::var("MUSE_SETTINGS_PATH")
+            .map_or_else(|_| PathBuf::from(DEFAULT_SETTINGS_PATH), PathBuf::from);
+
+        let guilds = load_guild_map(&path)?;
+
+        Ok(Self {
+            path,
+            guilds: Arc::new(Mutex::new(guilds)),
+            saving: Arc::new(AsyncMutex::new(())),
+        })
+    }
+
+    pub(crate) fn get(&self, guild_id: GuildId) -> GuildSettings {
+        self.guilds
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Change the settings for `guild_id` with `f`, then save every guild's settings.
+    ///
+    /// The change is only kept once it's been saved, so a failed save leaves them as they were.
+    pub(crate) async fn update<T>(
+        &self,
+        guild_id: GuildId,
+        f: impl FnOnce(&mut GuildSettings) -> T,
+    ) -> Result<T> {
+        let _saving = self.saving.lock().await;
+        let mut updated = self.guilds.lock().unwrap().clone();
+        let result = f(updated.entry(guild_id).or_default());
+
+        let path = self.path.clone();
+        let updated =
+            task::spawn_blocking(move || save_guild_map(&path, &updated).map(|()| updated))
+                .await??;
+        *self.guilds.lock().unwrap() = updated;
+        Ok(result)
+    }
+}
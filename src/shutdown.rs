@@ -0,0 +1,102 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use log::{error, info, warn};
+use poise::serenity_prelude::{Http, ShardManager};
+use songbird::Songbird;
+use tokio::{
+    signal,
+    sync::Mutex,
+    time::{timeout_at, Instant},
+};
+
+use crate::types::Data;
+
+/// How long to spend shutting down before giving up and exiting anyway.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait for SIGINT, or SIGTERM on Unix.
+pub(crate) async fn wait_for_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Error while listening for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        res = signal::ctrl_c() => {
+            if let Err(e) = res {
+                error!("Error while listening for SIGINT: {e}");
+            }
+        }
+        _ = terminate => {}
+    }
+}
+
+/// Stop taking commands, leave every call and disconnect from Discord.
+pub(crate) async fn shutdown(
+    manager: Arc<Songbird>,
+    http: Arc<Http>,
+    shard_manager: Arc<Mutex<ShardManager>>,
+    data: Data,
+) {
+    info!("Shutting down...");
+    data.shutting_down.store(true, Ordering::SeqCst);
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+
+    if timeout_at(deadline, leave_calls(&manager, &http, &data))
+        .await
+        .is_err()
+    {
+        warn!("Timed out while leaving voice channels.");
+    }
+
+    if timeout_at(deadline, async {
+        shard_manager.lock().await.shutdown_all().await;
+    })
+    .await
+    .is_err()
+    {
+        warn!("Timed out while disconnecting from Discord.");
+    }
+
+    info!("Shut down.");
+    log::logger().flush();
+}
+
+async fn leave_calls(manager: &Songbird, http: &Http, data: &Data) {
+    for guild_id in data.guilds.guild_ids() {
+        let Some(call) = manager.get(guild_id) else {
+            continue;
+        };
+
+        // Leaving would otherwise be saved as an empty queue.
+        data.snapshots
+            .save(&call, guild_id, &data.guilds, &data.settings)
+            .await;
+
+        if let Some(channel) = data.guilds.with(guild_id, |g| g.announce_channel) {
+            if let Err(e) = channel
+                .say(http, "Restarting, I'll be back in a moment.")
+                .await
+            {
+                error!("Error sending restart notification in {channel}: {e}");
+            }
+        }
+
+        if let Err(e) = manager.remove(guild_id).await {
+            error!("Error while leaving {guild_id}: {e}");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{debug, error, info};
+use poise::{
+    async_trait,
+    serenity_prelude::{ChannelId, Context as SerenityContext, GuildId, UserId},
+};
+use serde::{Deserialize, Serialize};
+use songbird::{input::Metadata, Call, Event, EventContext, EventHandler};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::{
+    event::add_call_events,
+    filters::GuildFilters,
+    local::{lazy_song_input, song_input},
+    settings::SettingsStore,
+    state::{GuildStore, LoopMode},
+    store::{load_guild_map, save_guild_map},
+    track::{requester, seek_anchor, set_seek_anchor, SeekAnchor},
+    types::{Context, Data},
+};
+
+const DEFAULT_QUEUE_PATH: &str = "queues.json";
+/// How often the current track's position is saved.
+pub(crate) const SNAPSHOT_PERIOD: Duration = Duration::from_secs(10);
+
+/// A queued song, as much as is needed to queue it again.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct SavedTrack {
+    pub(crate) url: String,
+    /// Shown in the queue until the song comes up, so only the first song is resolved on restore.
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    #[serde(default)]
+    pub(crate) duration: Option<Duration>,
+    pub(crate) requester: Option<UserId>,
+}
+
+impl SavedTrack {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            title: self.title.clone(),
+            source_url: Some(self.url.clone()),
+            duration: self.duration,
+            ..Default::default()
+        }
+    }
+}
+
+/// Everything needed to pick a guild's playback back up after a restart.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct QueueSnapshot {
+    pub(crate) channel: ChannelId,
+    pub(crate) announce_channel: Option<ChannelId>,
+    pub(crate) tracks: Vec<SavedTrack>,
+    /// Position in the first track.
+    pub(crate) position: Duration,
+    pub(crate) loop_mode: LoopMode,
+    pub(crate) volume: f32,
+    #[serde(default)]
+    pub(crate) volume_chosen: bool,
+}
+
+/// Shared store of [`QueueSnapshot`]s, saved to a JSON file whenever they change.
+#[derive(Clone, Debug)]
+pub(crate) struct SnapshotStore {
+    path: PathBuf,
+    guilds: Arc<Mutex<HashMap<GuildId, QueueSnapshot>>>,
+}
+
+impl SnapshotStore {
+    /// Load the snapshot file named by `MUSE_QUEUE_PATH`, starting empty if it doesn't exist.
+    pub(crate) fn load() -> Result<Self> {
+        let path = env::var("MUSE_QUEUE_PATH")
+            .map_or_else(|_| PathBuf::from(DEFAULT_QUEUE_PATH), PathBuf::from);
+
+        let guilds = load_guild_map(&path)?;
+
+        Ok(Self {
+            path,
+            guilds: Arc::new(Mutex::new(guilds)),
+        })
+    }
+
+    /// Take every snapshot out of the store, leaving it empty.
+    pub(crate) fn take_all(&self) -> HashMap<GuildId, QueueSnapshot> {
+        std::mem::take(&mut *self.guilds.lock().unwrap())
+    }
+
+    /// Replace or remove the snapshot for `guild_id`, then save every guild's snapshots.
+    pub(crate) fn set(&self, guild_id: GuildId, snapshot: Option<QueueSnapshot>) {
+        let mut guilds = self.guilds.lock().unwrap();
+        match snapshot {
+            Some(snapshot) => guilds.insert(guild_id, snapshot),
+            None => guilds.remove(&guild_id),
+        };
+
+        if let Err(e) = save_guild_map(&self.path, &guilds) {
+            error!("Error while saving queue snapshots: {e}");
+        }
+    }
+
+    /// Snapshot `call`'s queue, if `guild_id` has opted in to restoring it.
+    pub(crate) async fn save(
+        &self,
+        call: &AsyncMutex<Call>,
+        guild_id: GuildId,
+        guilds: &GuildStore,
+        settings: &SettingsStore,
+    ) {
+        if !settings.get(guild_id).restore_queue() {
+            return;
+        }
+
+        let (channel, queue) = {
+            let call = call.lock().await;
+            (call.current_channel(), call.queue().current_queue())
+        };
+        let Some(channel) = channel else {
+            return;
+        };
+
+        let speed = guilds.with(guild_id, |g| g.filters.speed_factor());
+        let position = match queue.first() {
+            Some(np) => np.get_info().await.map_or(Duration::ZERO, |info| {
+                seek_anchor(np, speed).song_position(info.position)
+            }),
+            None => Duration::ZERO,
+        };
+        let tracks = queue
+            .iter()
+            .filter_map(|track| {
+                let metadata = track.metadata();
+                Some(SavedTrack {
+                    url: metadata.source_url.clone()?,
+                    title: metadata.title.clone(),
+                    duration: metadata.duration,
+                    requester: requester(track),
+                })
+            })
+            .collect();
+        let (announce_channel, loop_mode, volume, volume_chosen) = guilds.with(guild_id, |g| {
+            (g.announce_channel, g.loop_mode, g.volume, g.volume_chosen)
+        });
+
+        self.set(
+            guild_id,
+            Some(QueueSnapshot {
+                channel: ChannelId(channel.0),
+                announce_channel,
+                tracks,
+                position,
+                loop_mode,
+                volume,
+                volume_chosen,
+            }),
+        );
+    }
+}
+
+/// Snapshot the queue of the command's guild straight after the command changed it, rather than
+/// once a song starts or ends.
+pub(crate) async fn snapshot_queue(ctx: Context<'_>, call: &AsyncMutex<Call>) {
+    let data = ctx.data();
+    data.snapshots
+        .save(call, ctx.guild_id().unwrap(), &data.guilds, &data.settings)
+        .await;
+}
+
+/// Saves a snapshot of the queue whenever a track starts or ends, and periodically in between.
+pub(crate) struct QueueSnapshotter {
+    call: Weak<AsyncMutex<Call>>,
+    guild_id: GuildId,
+    guilds: GuildStore,
+    settings: SettingsStore,
+    snapshots: SnapshotStore,
+}
+
+impl QueueSnapshotter {
+    pub(crate) fn new(call: Weak<AsyncMutex<Call>>, guild_id: GuildId, data: &Data) -> Self {
+        Self {
+            call,
+            guild_id,
+            guilds: data.guilds.clone(),
+            settings: data.settings.clone(),
+            snapshots: data.snapshots.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for QueueSnapshotter {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let call = self.call.upgrade()?;
+        self.snapshots
+            .save(&call, self.guild_id, &self.guilds, &self.settings)
+            .await;
+        None
+    }
+}
+
+/// Rejoin the voice channels saved before the last shutdown and queue their songs again.
+pub(crate) async fn restore_queues(ctx: &SerenityContext, data: &Data) {
+    let Some(manager) = songbird::get(ctx).await else {
+        return;
+    };
+
+    for (guild_id, snapshot) in data.snapshots.take_all() {
+        if !data.settings.get(guild_id).restore_queue() || snapshot.tracks.is_empty() {
+            continue;
+        }
+
+        let (call, res) = manager.join(guild_id, snapshot.channel).await;
+        if let Err(e) = res {
+            error!("Error while rejoining {guild_id} to restore its queue: {e}");
+            // Keep it for the next restart, rather than losing the queue to a passing outage.
+            data.snapshots.set(guild_id, Some(snapshot));
+            continue;
+        }
+
+        data.guilds.with(guild_id, |g| {
+            g.announce_channel = snapshot.announce_channel;
+            g.loop_mode = snapshot.loop_mode;
+            g.volume = snapshot.volume;
+            g.volume_chosen = snapshot.volume_chosen;
+        });
+        add_call_events(
+            &mut *call.lock().await,
+            &call,
+            manager.clone(),
+            ctx,
+            data,
+            guild_id,
+        );
+
+        let mut restored = 0;
+        for (i, saved) in snapshot.tracks.iter().enumerate() {
+            let filters = GuildFilters::new(data, guild_id);
+            // The rest of the queue is resolved as it comes up, like a playlist's songs.
+            let song = match i {
+                0 => song_input(saved.url.clone(), filters).await,
+                _ => lazy_song_input(saved.metadata(), filters).await,
+            };
+            let song = match song {
+                Ok(song) => song,
+                Err(e) => {
+                    error!("Error while restoring `{}`: {e}", saved.url);
+                    continue;
+                }
+            };
+
+            let (track, handle) = data
+                .guilds
+                .with(guild_id, |g| g.create_track(song, saved.requester));
+            call.lock().await.enqueue(track);
+            restored += 1;
+
+            if i == 0 && !snapshot.position.is_zero() {
+                // It hasn't started yet, so it starts from here in the song.
+                match handle.seek_time(snapshot.position) {
+                    Ok(()) => {
+                        let anchor = SeekAnchor {
+                            output: snapshot.position,
+                            position: snapshot.position,
+                            speed: data.guilds.with(guild_id, |g| g.filters.speed_factor()),
+                        };
+                        set_seek_anchor(&handle, anchor).await;
+                    }
+                    Err(e) => error!("Error while seeking restored track: {e}"),
+                }
+            }
+        }
+
+        debug!("Restored {restored} songs in {guild_id}.");
+        if let Some(channel) = snapshot.announce_channel {
+            if let Err(e) = channel
+                .say(
+                    &ctx.http,
+                    format!("Restored {restored} songs after restarting."),
+                )
+                .await
+            {
+                error!("Error sending restore notification in {channel}: {e}");
+            }
+        }
+    }
+
+    info!("Finished restoring queues.");
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use poise::{
+    serenity_prelude::{ChannelId, GuildId, MessageId, UserId},
+    ChoiceParameter,
+};
+use serde::{Deserialize, Serialize};
+use songbird::{
+    input::Input,
+    tracks::{create_player, LoopState, Track, TrackHandle, TrackResult},
+    Event, TrackEvent,
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::{
+    filters::Filters,
+    prefetch::Prefetched,
+    track::{set_requester, set_seek_anchor, ResetSeekAnchor, SeekAnchor},
+};
+
+/// How playback repeats once a track ends.
+#[derive(ChoiceParameter, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum LoopMode {
+    #[default]
+    #[name = "off"]
+    Off,
+    #[name = "track"]
+    Track,
+    #[name = "queue"]
+    Queue,
+}
+
+impl LoopMode {
+    /// The mode after this one, cycling back to [`LoopMode::Off`].
+    pub(crate) fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Track,
+            Self::Track => Self::Queue,
+            Self::Queue => Self::Off,
+        }
+    }
+}
+
+/// Number of recently requested songs remembered per guild.
+const RECENT_SONGS_LEN: usize = 50;
+
+/// A song recently requested in a guild.
+#[derive(Clone, Debug)]
+pub(crate) struct RecentSong {
+    pub(crate) title: String,
+    pub(crate) url: String,
+}
+
+/// Number of played songs remembered per guild.
+const HISTORY_LEN: usize = 50;
+
+/// A song that was played in a guild.
+#[derive(Clone, Debug)]
+pub(crate) struct PlayedSong {
+    pub(crate) title: String,
+    pub(crate) url: String,
+    pub(crate) requester: Option<UserId>,
+    pub(crate) played_at: DateTime<Utc>,
+}
+
+/// Votes to skip a particular track.
+#[derive(Debug)]
+pub(crate) struct SkipVotes {
+    track: Uuid,
+    voters: HashSet<UserId>,
+}
+
+/// A place in line to add songs to a guild's queue, so songs resolved at once keep their order.
+#[derive(Debug)]
+pub(crate) struct EnqueueTurn {
+    previous: Option<oneshot::Receiver<()>>,
+    /// Dropped once this turn's songs are queued or given up on, letting the next turn go.
+    _done: oneshot::Sender<()>,
+}
+
+impl EnqueueTurn {
+    /// Wait until every song requested before this turn has been queued or given up on.
+    pub(crate) async fn wait(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            let _ = previous.await;
+        }
+    }
+}
+
+/// Per-guild playback state.
+#[derive(Debug)]
+pub(crate) struct GuildState {
+    /// Volume applied to every track, where `1.0` is 100%.
+    pub(crate) volume: f32,
+    /// Whether `volume` was chosen with `/volume`, rather than left at the guild's default.
+    pub(crate) volume_chosen: bool,
+    pub(crate) loop_mode: LoopMode,
+    /// Recently requested songs, most recent first.
+    pub(crate) recent_songs: VecDeque<RecentSong>,
+    /// Played songs, most recent first.
+    pub(crate) history: VecDeque<PlayedSong>,
+    /// Channel where playback notifications are sent.
+    pub(crate) announce_channel: Option<ChannelId>,
+    /// Channel the bot was last summoned from, where notifications go unless one is set.
+    pub(crate) summoned_channel: Option<ChannelId>,
+    pub(crate) skip_votes: Option<SkipVotes>,
+    /// The latest now-playing message, whose buttons control playback.
+    pub(crate) now_playing_panel: Option<(ChannelId, MessageId)>,
+    /// The track last announced as now playing, which resuming shouldn't announce again.
+    pub(crate) announced_track: Option<Uuid>,
+    /// Number of `/nowplaying` replies currently being kept up to date.
+    pub(crate) live_now_playing: usize,
+    /// Audio effects applied to every track as it starts.
+    pub(crate) filters: Filters,
+    /// The next song, started early so it plays without a gap.
+    pub(crate) prefetched: Option<Prefetched>,
+    /// The song muted while the current one fades out, to be faded in once it starts.
+    pub(crate) fading: Option<Uuid>,
+    /// The URL of a song being restarted, and where in it to restart, since songbird asks for
+    /// the time played instead.
+    pub(crate) restart_from: Option<(String, Duration)>,
+    /// Released once the latest [`EnqueueTurn`] is over.
+    last_enqueue: Option<oneshot::Receiver<()>>,
+}
+
+impl Default for GuildState {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            volume_chosen: false,
+            loop_mode: LoopMode::Off,
+            recent_songs: VecDeque::new(),
+            history: VecDeque::new(),
+            announce_channel: None,
+            summoned_channel: None,
+            skip_votes: None,
+            now_playing_panel: None,
+            announced_track: None,
+            live_now_playing: 0,
+            filters: Filters::default(),
+            prefetched: None,
+            fading: None,
+            restart_from: None,
+            last_enqueue: None,
+        }
+    }
+}
+
+impl GuildState {
+    /// Register `user`'s vote to skip `track`, returning whether it's a new vote and the total.
+    pub(crate) fn vote_skip(&mut self, track: Uuid, user: UserId) -> (bool, usize) {
+        let votes = match &mut self.skip_votes {
+            Some(votes) if votes.track == track => votes,
+            votes => votes.insert(SkipVotes {
+                track,
+                voters: HashSet::new(),
+            }),
+        };
+        let new = votes.voters.insert(user);
+        (new, votes.voters.len())
+    }
+
+    /// Remember a requested song so it can be suggested again later.
+    pub(crate) fn remember_song(&mut self, title: &str, url: &str) {
+        self.recent_songs.retain(|recent| recent.url != url);
+        self.recent_songs.push_front(RecentSong {
+            title: title.to_string(),
+            url: url.to_string(),
+        });
+        self.recent_songs.truncate(RECENT_SONGS_LEN);
+    }
+
+    /// Record a song that has finished playing.
+    pub(crate) fn remember_played(&mut self, song: PlayedSong) {
+        self.history.push_front(song);
+        self.history.truncate(HISTORY_LEN);
+    }
+
+    /// Take the next turn to add songs to the queue.
+    pub(crate) fn enqueue_turn(&mut self) -> EnqueueTurn {
+        let (done, next) = oneshot::channel();
+        EnqueueTurn {
+            previous: self.last_enqueue.replace(next),
+            _done: done,
+        }
+    }
+
+    /// Create a track from `source` with this guild's playback settings applied.
+    ///
+    /// Tracks without a `requester` belong to nobody, rather than to whoever queued them again.
+    pub(crate) fn create_track(
+        &self,
+        source: Input,
+        requester: Option<UserId>,
+    ) -> (Track, TrackHandle) {
+        let (mut track, handle) = create_player(source);
+        if let Some(requester) = requester {
+            set_requester(&handle, requester);
+        }
+        let _ = handle.add_event(Event::Track(TrackEvent::Loop), ResetSeekAnchor);
+        track.set_volume(self.volume);
+        if self.loop_mode == LoopMode::Track {
+            // Only fails for unseekable sources, which simply won't loop.
+            let _ = track.set_loops(LoopState::Infinite);
+        }
+        (track, handle)
+    }
+}
+
+/// Shared store of [`GuildState`]s, cheap to clone into event handlers.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GuildStore(Arc<Mutex<HashMap<GuildId, GuildState>>>);
+
+impl GuildStore {
+    /// Run `f` with the state for `guild_id`, creating it if necessary.
+    pub(crate) fn with<T>(&self, guild_id: GuildId, f: impl FnOnce(&mut GuildState) -> T) -> T {
+        let mut guilds = self.0.lock().unwrap();
+        f(guilds.entry(guild_id).or_default())
+    }
+
+    /// Every guild with stored state.
+    pub(crate) fn guild_ids(&self) -> Vec<GuildId> {
+        self.0.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Restart `track`'s source at `position` in its song, with the current filters.
+    ///
+    /// Songbird only restarts a source when seeking back from where it is, so returns `false`
+    /// without seeking if the track hasn't played anything yet.
+    pub(crate) async fn restart_track(
+        &self,
+        guild_id: GuildId,
+        track: &TrackHandle,
+        position: Duration,
+    ) -> TrackResult<bool> {
+        let Some(url) = track.metadata().source_url.clone() else {
+            return Ok(false);
+        };
+        if track.get_info().await?.position.is_zero() {
+            return Ok(false);
+        }
+
+        let speed = self.with(guild_id, |g| {
+            g.restart_from = Some((url, position));
+            g.filters.speed_factor()
+        });
+        if let Err(e) = track.seek_time(Duration::ZERO) {
+            self.with(guild_id, |g| g.restart_from = None);
+            return Err(e);
+        }
+        let anchor = SeekAnchor {
+            output: Duration::ZERO,
+            position,
+            speed,
+        };
+        set_seek_anchor(track, anchor).await;
+        Ok(true)
+    }
+}
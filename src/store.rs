@@ -0,0 +1,42 @@
+use std::{collections::HashMap, fs, io::ErrorKind, path::Path};
+
+use anyhow::Result;
+use poise::serenity_prelude::GuildId;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Read a JSON file, or the default value if it doesn't exist.
+pub(crate) fn load_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    match fs::read_to_string(path) {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write a value to a JSON file.
+pub(crate) fn save_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value)?;
+    // Replace the file in one step so a crash can't leave it half-written.
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, json)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Read a JSON file of per-guild values, or nothing if it doesn't exist.
+pub(crate) fn load_guild_map<T: DeserializeOwned>(path: &Path) -> Result<HashMap<GuildId, T>> {
+    Ok(load_json::<HashMap<u64, T>>(path)?
+        .into_iter()
+        .map(|(guild_id, value)| (GuildId(guild_id), value))
+        .collect())
+}
+
+/// Write per-guild values to a JSON file.
+pub(crate) fn save_guild_map<T: Serialize>(path: &Path, map: &HashMap<GuildId, T>) -> Result<()> {
+    save_json(
+        path,
+        &map.iter()
+            .map(|(guild_id, value)| (guild_id.0, value))
+            .collect::<HashMap<_, _>>(),
+    )
+}
@@ -0,0 +1,321 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use poise::{async_trait, serenity_prelude::UserId};
+use songbird::{
+    input::Metadata, tracks::TrackHandle, typemap::TypeMapKey, Event, EventContext, EventHandler,
+};
+
+use crate::format::{format_duration, song_link};
+
+/// The user who requested a track, stored in its typemap.
+pub(crate) struct Requester;
+
+impl TypeMapKey for Requester {
+    type Value = UserId;
+}
+
+pub(crate) fn set_requester(track: &TrackHandle, user: UserId) {
+    // Only fails if the typemap is being written to, which never takes long.
+    if let Ok(mut typemap) = track.typemap().try_write() {
+        typemap.insert::<Requester>(user);
+    }
+}
+
+pub(crate) fn requester(track: &TrackHandle) -> Option<UserId> {
+    track.typemap().try_read().ok()?.get::<Requester>().copied()
+}
+
+/// Where a track's source last restarted, stored in its typemap.
+///
+/// Songbird counts a track's position in audio played, which only matches the song's own time
+/// until it's sped up or slowed down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct SeekAnchor {
+    /// Songbird's position when the source restarted.
+    pub(crate) output: Duration,
+    /// The position in the song the source restarted at.
+    pub(crate) position: Duration,
+    /// The speed factor the source was started with.
+    pub(crate) speed: f64,
+}
+
+impl TypeMapKey for SeekAnchor {
+    type Value = SeekAnchor;
+}
+
+impl SeekAnchor {
+    /// The anchor of a track that's been playing at `speed` since the beginning.
+    pub(crate) fn start(speed: f64) -> Self {
+        Self {
+            output: Duration::ZERO,
+            position: Duration::ZERO,
+            speed,
+        }
+    }
+
+    /// Where in the song songbird's `output` position is.
+    pub(crate) fn song_position(&self, output: Duration) -> Duration {
+        self.position + output.saturating_sub(self.output).mul_f64(self.speed)
+    }
+
+    /// Songbird's position once the song reaches `position`, which mustn't be before the anchor.
+    ///
+    /// `None` if that's too far in to count.
+    pub(crate) fn output_position(&self, position: Duration) -> Option<Duration> {
+        let offset = position.saturating_sub(self.position).as_secs_f64() / self.speed;
+        self.output
+            .checked_add(Duration::try_from_secs_f64(offset).ok()?)
+    }
+}
+
+pub(crate) async fn set_seek_anchor(track: &TrackHandle, anchor: SeekAnchor) {
+    track.typemap().write().await.insert::<SeekAnchor>(anchor);
+}
+
+/// `track`'s anchor, or its start if it hasn't restarted since it was started at `speed`.
+pub(crate) fn seek_anchor(track: &TrackHandle, speed: f64) -> SeekAnchor {
+    track
+        .typemap()
+        .try_read()
+        .ok()
+        .and_then(|typemap| typemap.get::<SeekAnchor>().copied())
+        .unwrap_or_else(|| SeekAnchor::start(speed))
+}
+
+/// Forgets a track's [`SeekAnchor`] when it loops back to the beginning.
+pub(crate) struct ResetSeekAnchor;
+
+#[async_trait]
+impl EventHandler for ResetSeekAnchor {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(tracks) = ctx {
+            for (_, handle) in *tracks {
+                handle.typemap().write().await.remove::<SeekAnchor>();
+            }
+        }
+        None
+    }
+}
+
+/// What's shown about a track, with fallbacks for anything its extractor didn't provide.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TrackInfo {
+    pub(crate) title: String,
+    pub(crate) url: Option<String>,
+    /// `None` for livestreams and anything else of unknown length.
+    pub(crate) duration: Option<Duration>,
+    /// The artist, or failing that the uploader's channel.
+    pub(crate) author: Option<String>,
+    pub(crate) thumbnail: Option<String>,
+    pub(crate) upload_date: Option<NaiveDate>,
+}
+
+impl TrackInfo {
+    pub(crate) const UNKNOWN_TITLE: &'static str = "Unknown title";
+
+    pub(crate) fn of(track: &TrackHandle) -> Self {
+        Self::from(track.metadata())
+    }
+
+    /// The title, linked to the track's source if it has one that can be opened.
+    pub(crate) fn link(&self) -> String {
+        song_link(&self.title, self.url.as_deref())
+    }
+
+    /// The duration, or `LIVE` if it isn't known.
+    pub(crate) fn duration_text(&self) -> String {
+        self.duration
+            .as_ref()
+            .map_or_else(|| "LIVE".to_string(), format_duration)
+    }
+}
+
+impl From<&Metadata> for TrackInfo {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            title: metadata
+                .title
+                .clone()
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| Self::UNKNOWN_TITLE.to_string()),
+            url: metadata.source_url.clone(),
+            duration: metadata.duration.filter(|duration| !duration.is_zero()),
+            author: metadata.artist.clone().or_else(|| metadata.channel.clone()),
+            thumbnail: metadata.thumbnail.clone(),
+            upload_date: metadata.date.as_deref().and_then(parse_upload_date),
+        }
+    }
+}
+
+/// Parse a `YYYYMMDD` date, as given by yt-dlp.
+fn parse_upload_date(date: &str) -> Option<NaiveDate> {
+    if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let year = date.get(0..4)?.parse().ok()?;
+    let month = date.get(4..6)?.parse().ok()?;
+    let day = date.get(6..8)?.parse().ok()?;
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            title: Some("Song".to_string()),
+            source_url: Some("https://example.com/song".to_string()),
+            duration: Some(Duration::from_secs(90)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn seek_anchor_maps_positions() {
+        let start = SeekAnchor::start(1.5);
+        assert_eq!(
+            start.song_position(Duration::from_secs(10)),
+            Duration::from_secs(15)
+        );
+        assert_eq!(
+            start.output_position(Duration::from_secs(15)),
+            Some(Duration::from_secs(10))
+        );
+
+        let restarted = SeekAnchor {
+            output: Duration::ZERO,
+            position: Duration::from_secs(60),
+            speed: 2.0,
+        };
+        assert_eq!(
+            restarted.song_position(Duration::ZERO),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            restarted.song_position(Duration::from_secs(5)),
+            Duration::from_secs(70)
+        );
+        assert_eq!(
+            restarted.output_position(Duration::from_secs(70)),
+            Some(Duration::from_secs(5))
+        );
+        let slowed = SeekAnchor {
+            speed: 0.5,
+            ..restarted
+        };
+        assert_eq!(slowed.output_position(Duration::MAX), None);
+        let late = SeekAnchor {
+            output: Duration::MAX,
+            ..restarted
+        };
+        assert_eq!(late.output_position(Duration::from_secs(70)), None);
+    }
+
+    #[test]
+    fn missing_or_blank_title_is_unknown() {
+        for title in [None, Some(""), Some("   ")] {
+            let info = TrackInfo::from(&Metadata {
+                title: title.map(str::to_string),
+                ..metadata()
+            });
+            assert_eq!(info.title, TrackInfo::UNKNOWN_TITLE);
+        }
+    }
+
+    #[test]
+    fn link_needs_a_url() {
+        let info = TrackInfo::from(&metadata());
+        assert_eq!(info.link(), "[Song](https://example.com/song)");
+
+        let info = TrackInfo::from(&Metadata {
+            source_url: None,
+            ..metadata()
+        });
+        assert_eq!(info.link(), "Song");
+
+        let info = TrackInfo::from(&Metadata::default());
+        assert_eq!(info.link(), TrackInfo::UNKNOWN_TITLE);
+
+        let info = TrackInfo::from(&Metadata {
+            source_url: Some("file:///music/song.mp3".to_string()),
+            ..metadata()
+        });
+        assert_eq!(info.link(), "Song");
+    }
+
+    #[test]
+    fn unknown_or_zero_duration_is_live() {
+        assert_eq!(TrackInfo::from(&metadata()).duration_text(), "1:30");
+
+        for duration in [None, Some(Duration::ZERO)] {
+            let info = TrackInfo::from(&Metadata {
+                duration,
+                ..metadata()
+            });
+            assert_eq!(info.duration, None);
+            assert_eq!(info.duration_text(), "LIVE");
+        }
+    }
+
+    #[test]
+    fn author_prefers_artist_over_channel() {
+        let both = Metadata {
+            artist: Some("Artist".to_string()),
+            channel: Some("Channel".to_string()),
+            ..metadata()
+        };
+        assert_eq!(TrackInfo::from(&both).author.as_deref(), Some("Artist"));
+
+        let channel_only = Metadata {
+            artist: None,
+            ..both.clone()
+        };
+        assert_eq!(
+            TrackInfo::from(&channel_only).author.as_deref(),
+            Some("Channel")
+        );
+
+        let artist_only = Metadata {
+            channel: None,
+            ..both
+        };
+        assert_eq!(
+            TrackInfo::from(&artist_only).author.as_deref(),
+            Some("Artist")
+        );
+
+        assert_eq!(TrackInfo::from(&metadata()).author, None);
+    }
+
+    #[test]
+    fn upload_dates() {
+        assert_eq!(
+            parse_upload_date("20230415"),
+            NaiveDate::from_ymd_opt(2023, 4, 15)
+        );
+        assert_eq!(
+            TrackInfo::from(&Metadata {
+                date: Some("20230415".to_string()),
+                ..metadata()
+            })
+            .upload_date,
+            NaiveDate::from_ymd_opt(2023, 4, 15)
+        );
+
+        for date in [
+            "",
+            "2023130",
+            "20231340",
+            "20231301",
+            "20230230",
+            "202304150",
+            "2023+4-1",
+            "abcdefgh",
+        ] {
+            assert_eq!(parse_upload_date(date), None, "{date}");
+        }
+        assert_eq!(TrackInfo::from(&metadata()).upload_date, None);
+    }
+}
@@ -1,8 +1,26 @@
-pub(crate) struct Data;
+use std::sync::{atomic::AtomicBool, Arc};
+
+use crate::{
+    cache::AudioCache, loudness::LoudnessStore, playlists::PlaylistStore, settings::SettingsStore,
+    snapshot::SnapshotStore, state::GuildStore,
+};
+
+#[derive(Clone)]
+pub(crate) struct Data {
+    pub(crate) guilds: GuildStore,
+    pub(crate) settings: SettingsStore,
+    pub(crate) snapshots: SnapshotStore,
+    pub(crate) playlists: PlaylistStore,
+    pub(crate) loudness: LoudnessStore,
+    /// Downloaded songs, if `MUSE_CACHE_DIR` is set.
+    pub(crate) cache: Option<AudioCache>,
+    /// Set once the bot starts shutting down, after which commands are refused.
+    pub(crate) shutting_down: Arc<AtomicBool>,
+}
+
 pub(crate) type Error = anyhow::Error;
 pub(crate) type Context<'a> = poise::Context<'a, Data, Error>;
 pub(crate) type FrameworkError<'a> = poise::FrameworkError<'a, Data, Error>;
 pub(crate) type Result<T> = anyhow::Result<T>;
 
 pub(crate) const SONGBIRD_MANAGER_ERR: &str = "Failed to acquire Songbird manager.";
-pub(crate) const PAGE_SIZE: usize = 5;
@@ -0,0 +1,372 @@
+use std::{
+    ffi::OsStr,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use poise::async_trait;
+use serde_json::Value;
+use songbird::input::{
+    children_to_reader,
+    error::{Error as InputError, Result as InputResult},
+    restartable::Restart,
+    Codec, Container, Input, Metadata, Restartable,
+};
+use tokio::process::Command as TokioCommand;
+
+use crate::{
+    filters::{FilterChain, GuildFilters},
+    types::*,
+};
+
+pub(crate) const YTDL_COMMAND: &str = "yt-dlp";
+
+/// A playlist enumerated by yt-dlp or saved with `/playlist`, without resolving any of its entries.
+pub(crate) struct Playlist {
+    pub(crate) title: Option<String>,
+    pub(crate) url: Option<String>,
+    pub(crate) entries: Vec<Metadata>,
+}
+
+pub(crate) fn is_url(query: &str) -> bool {
+    query.starts_with("https://") || query.starts_with("http://")
+}
+
+pub(crate) fn is_playlist_url(query: &str) -> bool {
+    is_url(query) && (query.contains("list=") || query.contains("/playlist"))
+}
+
+pub(crate) async fn enumerate_playlist(url: &str) -> Result<Playlist> {
+    enumerate_playlist_with(YTDL_COMMAND, url).await
+}
+
+/// Enumerate a playlist using `program`, which must accept yt-dlp's flat-playlist arguments.
+pub(crate) async fn enumerate_playlist_with(
+    program: impl AsRef<OsStr>,
+    url: &str,
+) -> Result<Playlist> {
+    let output = TokioCommand::new(program)
+        .args([
+            "--flat-playlist",
+            "-J",
+            "--ignore-config",
+            "--no-warnings",
+            url,
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        bail!(
+            "yt-dlp failed to enumerate playlist ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let value: Value = serde_json::from_slice(&output.stdout)?;
+    let entries = value
+        .get("entries")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("yt-dlp output is not a playlist."))?
+        .iter()
+        .filter_map(entry_metadata)
+        .collect();
+
+    Ok(Playlist {
+        title: value
+            .get("title")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        url: Some(url.to_string()),
+        entries,
+    })
+}
+
+/// Search YouTube for up to `count` songs, without resolving their streams.
+pub(crate) async fn search_songs(query: &str, count: usize) -> Result<Vec<Metadata>> {
+    let results = enumerate_playlist(&format!("ytsearch{count}:{query}")).await?;
+    Ok(results.entries)
+}
+
+fn entry_metadata(entry: &Value) -> Option<Metadata> {
+    let mut metadata = Metadata::from_ytdl_output(entry.clone());
+    // Flat entries only carry `url`, not `webpage_url`.
+    if metadata.source_url.is_none() {
+        metadata.source_url = entry.get("url").and_then(Value::as_str).map(str::to_string);
+    }
+    metadata.source_url.is_some().then_some(metadata)
+}
+
+/// Fetch the metadata for `url`, or the first result of a `ytsearch1:` query.
+pub(crate) async fn ytdl_metadata(url: &str) -> InputResult<Metadata> {
+    let output = TokioCommand::new(YTDL_COMMAND)
+        .args([
+            "-j",
+            "-f",
+            "webm[abr>0]/bestaudio/best",
+            "--no-playlist",
+            "--ignore-config",
+            "--no-warnings",
+            url,
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(InputError::YouTubeDlRun(output));
+    }
+
+    let json = output
+        .stdout
+        .split(|&b| b == b'\n')
+        .next()
+        .unwrap_or_default();
+    let value = serde_json::from_slice(json).map_err(|error| InputError::Json {
+        error,
+        parsed_text: String::from_utf8_lossy(json).into_owned(),
+    })?;
+    Ok(Metadata::from_ytdl_output(value))
+}
+
+/// Whether a source (re)started at `start` plays the song from its beginning.
+///
+/// Songbird starts queued tracks from zero rather than from no particular time, so both count.
+pub(crate) fn from_beginning(start: Option<Duration>) -> bool {
+    start.unwrap_or_default().is_zero()
+}
+
+/// Start ffmpeg decoding `input` into the PCM songbird expects, applying `filters` if given.
+pub(crate) fn spawn_ffmpeg(
+    input: impl AsRef<OsStr>,
+    stdin: Stdio,
+    start: Option<Duration>,
+    chain: &FilterChain,
+) -> InputResult<Child> {
+    let mut ffmpeg = Command::new("ffmpeg");
+    if let Some(start) = start {
+        ffmpeg.args(["-ss", &format!("{:.3}", start.as_secs_f64())]);
+    }
+    ffmpeg.arg("-i").arg(input);
+    if let Some(filters) = &chain.filters {
+        ffmpeg.args(["-af", filters]);
+    }
+    Ok(ffmpeg
+        .args([
+            "-f",
+            "s16le",
+            "-ac",
+            "2",
+            "-ar",
+            "48000",
+            "-acodec",
+            "pcm_f32le",
+            "-",
+        ])
+        .stdin(stdin)
+        .stderr(if chain.measures_loudness {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .spawn()?)
+}
+
+/// Stream `url` through yt-dlp and ffmpeg with the guild's `filters`, starting at `start` if given.
+///
+/// Songs in the guild's audio cache are read from disk instead.
+pub(crate) async fn ytdl_from(
+    url: &str,
+    start: Option<Duration>,
+    filters: &GuildFilters,
+) -> InputResult<Input> {
+    if let Some(cache) = &filters.cache {
+        if let Some(path) = cache.open(url, start).await {
+            let chain = filters.chain(url, start);
+            let mut ffmpeg = spawn_ffmpeg(&path, Stdio::null(), start, &chain)?;
+            filters.record_loudness(url, &chain, &mut ffmpeg);
+            return Ok(Input::new(
+                true,
+                children_to_reader::<f32>(vec![ffmpeg]),
+                Codec::FloatPcm,
+                Container::Raw,
+                None,
+            ));
+        }
+    }
+
+    let mut ytdl = Command::new(YTDL_COMMAND)
+        .args([
+            "-f",
+            "webm[abr>0]/bestaudio/best",
+            "-R",
+            "infinite",
+            "--no-playlist",
+            "--ignore-config",
+            "--no-warnings",
+            url,
+            "-o",
+            "-",
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let stdout = ytdl.stdout.take().ok_or(InputError::Stdout)?;
+
+    let chain = filters.chain(url, start);
+    let mut ffmpeg = spawn_ffmpeg("-", stdout.into(), start, &chain)?;
+    filters.record_loudness(url, &chain, &mut ffmpeg);
+
+    Ok(Input::new(
+        true,
+        children_to_reader::<f32>(vec![ytdl, ffmpeg]),
+        Codec::FloatPcm,
+        Container::Raw,
+        None,
+    ))
+}
+
+/// A yt-dlp source that picks up its guild's filters whenever it starts or restarts.
+pub(crate) struct LazyYtdl {
+    metadata: Metadata,
+    /// Whether `metadata` came from yt-dlp, rather than just holding the URL to ask it about.
+    resolved: bool,
+    filters: GuildFilters,
+}
+
+impl LazyYtdl {
+    /// A source with metadata known up front, so it can be queued without running yt-dlp.
+    pub(crate) fn new(metadata: Metadata, filters: GuildFilters) -> Self {
+        Self {
+            metadata,
+            resolved: true,
+            filters,
+        }
+    }
+
+    /// A source for `url` or a `ytsearch1:` query, whose metadata is fetched when it's created.
+    pub(crate) fn from_url(url: String, filters: GuildFilters) -> Self {
+        Self {
+            metadata: Metadata {
+                source_url: Some(url),
+                ..Default::default()
+            },
+            resolved: false,
+            filters,
+        }
+    }
+}
+
+/// Create a restartable source for `url` or a `ytsearch1:` query.
+pub(crate) async fn ytdl_input(url: String, filters: GuildFilters) -> InputResult<Input> {
+    Ok(Restartable::new(LazyYtdl::from_url(url, filters), true)
+        .await?
+        .into())
+}
+
+#[async_trait]
+impl Restart for LazyYtdl {
+    async fn call_restart(&mut self, time: Option<Duration>) -> InputResult<Input> {
+        let url = self
+            .metadata
+            .source_url
+            .as_deref()
+            .ok_or(InputError::Metadata)?;
+        let time = self.filters.restart_time(url, time);
+        if let Some(cache) = &self.filters.cache {
+            // Streamed this time, and played from disk next time, unless it's a livestream.
+            if self
+                .metadata
+                .duration
+                .is_some_and(|duration| !duration.is_zero())
+            {
+                cache.fetch(url);
+            }
+        }
+        if from_beginning(time) {
+            if let Some(input) = self.filters.take_prefetched(url) {
+                return Ok(input);
+            }
+        }
+        ytdl_from(url, time, &self.filters).await
+    }
+
+    async fn lazy_init(&mut self) -> InputResult<(Option<Metadata>, Codec, Container)> {
+        if !self.resolved {
+            let url = self
+                .metadata
+                .source_url
+                .as_deref()
+                .ok_or(InputError::Metadata)?;
+            let cached = self.filters.cache.as_ref().and_then(|cache| cache.get(url));
+            let mut metadata = match cached {
+                Some(song) => song.metadata(),
+                None => ytdl_metadata(url).await?,
+            };
+            metadata.source_url.get_or_insert_with(|| url.to_string());
+            self.metadata = metadata;
+            self.resolved = true;
+        }
+        Ok((Some(self.metadata.clone()), Codec::FloatPcm, Container::Raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAKE_YTDL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/fake-yt-dlp");
+
+    #[tokio::test]
+    async fn enumerates_playlist_entries() {
+        let playlist = enumerate_playlist_with(FAKE_YTDL, "https://example.com/playlist")
+            .await
+            .unwrap();
+
+        assert_eq!(playlist.title.as_deref(), Some("Fake playlist"));
+        assert_eq!(
+            playlist.url.as_deref(),
+            Some("https://example.com/playlist")
+        );
+
+        // The entry without a URL can't be played, so it's left out.
+        let entries: Vec<_> = playlist
+            .entries
+            .iter()
+            .map(|entry| (entry.title.as_deref(), entry.source_url.as_deref()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (Some("First"), Some("https://example.com/watch?v=aaa")),
+                (Some("Third"), Some("https://example.com/watch?v=ccc")),
+            ]
+        );
+        assert_eq!(playlist.entries[0].duration, Some(Duration::from_secs(61)));
+    }
+
+    #[tokio::test]
+    async fn reports_ytdl_errors() {
+        let error = enumerate_playlist_with(FAKE_YTDL, "https://example.com/private")
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("This playlist is private"));
+    }
+
+    #[test]
+    fn detects_urls() {
+        assert!(is_url("https://youtu.be/dQw4w9WgXcQ"));
+        assert!(!is_url("never gonna give you up"));
+        assert!(is_playlist_url(
+            "https://www.youtube.com/playlist?list=PL1234"
+        ));
+        assert!(!is_playlist_url("https://youtu.be/dQw4w9WgXcQ"));
+    }
+}